@@ -0,0 +1,129 @@
+/// GET 吞吐量测试 - 活跃文件与归档文件的读取性能
+/// dorea.examples.get-bench
+///
+/// 在你运行这个 Demo 之前，请确保 Dorea 服务已经正常启动！
+///
+/// 本示例展示：
+/// - 多个客户端使用 Pipeline 并发读取（减少网络往返，让服务端读取成为瓶颈）
+/// - 读取仍在 active.db 中的数据
+/// - 合并（归档）之后读取 archive 文件中的数据
+///
+/// 分别对新旧两个版本的服务端运行本示例即可对比读取路径的改动；
+/// 在 `config.toml` 中设置 `mmap_archives = true` 可以对比归档文件的内存映射读取。
+use dorea::client::DoreaClient;
+use dorea::network::NetPacketState;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const GROUP: &str = "get-bench";
+
+/// 预先写入的数据量（注意：单数据库默认上限 25600）
+const TOTAL_RECORDS: usize = 20000;
+
+/// 并发客户端数量
+const CONCURRENT_CLIENTS: usize = 4;
+
+/// 每个客户端执行的 GET 数量
+const READS_PER_CLIENT: usize = 50000;
+
+/// 每个 pipeline 批次的命令数
+const BATCH_SIZE: usize = 100;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut db = DoreaClient::connect(("127.0.0.1", 3450), "").await?;
+    db.select(GROUP).await?;
+    db.clean().await?;
+
+    println!("=== Dorea GET 吞吐量测试 ===\n");
+    println!("📝 数据量: {} 条", TOTAL_RECORDS);
+    println!("🧵 并发客户端数: {}", CONCURRENT_CLIENTS);
+    println!("📖 每客户端读取: {} 次\n", READS_PER_CLIENT);
+
+    // 写入测试数据
+    for batch_start in (0..TOTAL_RECORDS).step_by(BATCH_SIZE) {
+        let batch_end = (batch_start + BATCH_SIZE).min(TOTAL_RECORDS);
+        let commands: Vec<String> = (batch_start..batch_end)
+            .map(|i| format!("set key_{} \"value_{}\"", i, i))
+            .collect();
+        let cmd_refs: Vec<&str> = commands.iter().map(|s| s.as_str()).collect();
+        db.pipeline(&cmd_refs).await?;
+    }
+
+    println!("📊 测试 1: 读取活跃文件...");
+    let active_ops = bench().await?;
+
+    // 合并会先归档活跃文件，之后所有数据都从 archive 文件读取
+    println!("📦 合并数据库，将数据移入归档文件...");
+    db.execute(&format!("db merge {}", GROUP)).await?;
+    loop {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let (_, status) = db.execute(&format!("db merge {} status", GROUP)).await?;
+        let status: serde_json::Value = serde_json::from_slice(&status)?;
+        if status["running"] == false {
+            break;
+        }
+    }
+
+    println!("📊 测试 2: 读取归档文件...");
+    let archive_ops = bench().await?;
+
+    db.clean().await?;
+
+    println!("=== 对比结果 ===");
+    println!("活跃文件:    {:>10.2} ops/s", active_ops);
+    println!("归档文件:    {:>10.2} ops/s", archive_ops);
+
+    Ok(())
+}
+
+/// 多个客户端并发随机读取，返回每秒成功的 GET 数量
+async fn bench() -> anyhow::Result<f64> {
+    let total_success = Arc::new(AtomicU64::new(0));
+    let start_time = Instant::now();
+
+    let mut handles = Vec::new();
+
+    for client_id in 0..CONCURRENT_CLIENTS {
+        let success = Arc::clone(&total_success);
+
+        handles.push(tokio::spawn(async move {
+            let mut db = DoreaClient::connect(("127.0.0.1", 3450), "").await?;
+            db.select(GROUP).await?;
+
+            for batch in 0..READS_PER_CLIENT / BATCH_SIZE {
+                let commands: Vec<String> = (0..BATCH_SIZE)
+                    .map(|i| {
+                        // 简单的伪随机分布，避免所有客户端读取相同位置
+                        let n = (batch * BATCH_SIZE + i) * 7919 + client_id * 104729;
+                        format!("get key_{}", n % TOTAL_RECORDS)
+                    })
+                    .collect();
+                let cmd_refs: Vec<&str> = commands.iter().map(|s| s.as_str()).collect();
+
+                for (state, _) in db.pipeline(&cmd_refs).await? {
+                    if state == NetPacketState::OK {
+                        success.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+
+            anyhow::Ok(())
+        }));
+    }
+
+    for handle in handles {
+        handle.await??;
+    }
+
+    let elapsed = start_time.elapsed();
+    let success = total_success.load(Ordering::Relaxed);
+    let ops = success as f64 / elapsed.as_secs_f64();
+
+    println!("   ✅ 成功: {} 次", success);
+    println!("   ⏱️  耗时: {:?}", elapsed);
+    println!("   🚀 吞吐量: {:.2} ops/s\n", ops);
+
+    Ok(ops)
+}
@@ -929,12 +929,11 @@ pub async fn execute(command: &str, client: &mut DoreaClient) -> (NetPacketState
 
     match op.to_uppercase().as_str() {
         "GET" => {
-            if parts.len() != 1 {
-                return (NetPacketState::ERR, "Usage: GET <key>".into());
+            if parts.len() != 1 && !(parts.len() == 3 && parts[1] == "--at") {
+                return (NetPacketState::ERR, "Usage: GET <key> [--at <timestamp>]".into());
             }
-            let key = parts[0];
             // 直接发送命令给服务器
-            let command = format!("get {}", key);
+            let command = format!("get {}", parts.join(" "));
             match client.execute(&command).await {
                 Ok((state, data)) => {
                     if state == NetPacketState::OK {
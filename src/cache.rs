@@ -0,0 +1,231 @@
+//! 已解码数据的 LRU 缓存
+//!
+//! 每个库可以单独配置缓存容量（字节），缓存的是反序列化后的记录，
+//! 热点 key 的 GET 不需要再读取文件和解码。
+//!
+//! 缓存项记录了数据在文件中的位置：读取时只有位置与当前索引一致才算命中，
+//! 因此读取与写入并发时即使放入了旧数据，也不会被之后的读取返回。
+//! 写入任务在每次写入后移除对应的 key，及时释放内存。
+//!
+//! 所有库的缓存共享一个全局上限（`max_cache_memory`），并且只使用索引剩余的内存（`max_memory`），
+//! 超出时先淘汰本库最久未使用的数据。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use lru::LruCache;
+use serde_json::json;
+
+use crate::database::DataNode;
+use crate::memory;
+
+static MAX_CACHE_MEMORY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+// 每个缓存项的固定开销估算（链表节点、哈希表槽位与 DataNode 本身）
+const ENTRY_OVERHEAD: u64 = 96;
+
+/// 数据在文件中的位置 (file_id, start_position)
+pub(crate) type Location = (u32, u64);
+
+pub(crate) fn set_max_memory(bytes: u64) {
+    MAX_CACHE_MEMORY.store(bytes, Ordering::Relaxed);
+}
+
+#[derive(Debug)]
+struct Entry {
+    location: Location,
+    node: DataNode,
+    size: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    entries: LruCache<String, Entry>,
+    bytes: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.pop(key) {
+            self.release(entry.size);
+        }
+    }
+
+    fn pop_lru(&mut self) -> bool {
+        match self.entries.pop_lru() {
+            Some((_, entry)) => {
+                self.release(entry.size);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn release(&mut self, size: u64) {
+        self.bytes -= size;
+        memory::charge_cache(-(size as i64));
+    }
+}
+
+/// 某个库的数据缓存，容量为 0 时不缓存
+#[derive(Debug)]
+pub(crate) struct ValueCache {
+    capacity: u64,
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ValueCache {
+    pub(crate) fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                bytes: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// 查找 key，缓存的位置与 `location` 不一致时视为未命中
+    pub(crate) fn get(&self, key: &str, location: Location) -> Option<DataNode> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        let found = match inner.entries.get(key) {
+            Some(entry) if entry.location == location => Some(entry.node.clone()),
+            Some(_) => {
+                inner.remove(key);
+                None
+            }
+            None => None,
+        };
+        drop(inner);
+
+        match found {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        found
+    }
+
+    pub(crate) fn insert(&self, key: &str, location: Location, node: &DataNode) {
+        if !self.enabled() {
+            return;
+        }
+
+        let size = ENTRY_OVERHEAD + 2 * key.len() as u64 + node.value.size() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(key);
+
+        // 先满足本库容量，再满足全局上限；全局上限只能淘汰本库的数据，淘汰完仍不够时放弃缓存
+        while inner.bytes + size > self.capacity && inner.pop_lru() {}
+        while memory::cache() + size > MAX_CACHE_MEMORY.load(Ordering::Relaxed)
+            || memory::used() + size > memory::max()
+        {
+            if !inner.pop_lru() {
+                return;
+            }
+        }
+
+        inner.bytes += size;
+        memory::charge_cache(size as i64);
+        inner.entries.put(
+            key.to_string(),
+            Entry {
+                location,
+                node: node.clone(),
+                size,
+            },
+        );
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        if self.enabled() {
+            self.inner.lock().unwrap().remove(key);
+        }
+    }
+
+    /// 已缓存数据占用的内存
+    pub(crate) fn size(&self) -> u64 {
+        self.inner.lock().unwrap().bytes
+    }
+
+    pub(crate) fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        while inner.pop_lru() {}
+    }
+
+    pub(crate) fn report(&self, group: &str) -> serde_json::Value {
+        let (entries, bytes) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.entries.len(), inner.bytes)
+        };
+
+        json!({
+            "group": group,
+            "enabled": self.enabled(),
+            "capacity": self.capacity,
+            "size": bytes,
+            "entries": entries,
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "total_memory": memory::cache(),
+            "max_memory": MAX_CACHE_MEMORY.load(Ordering::Relaxed),
+        })
+    }
+}
+
+impl Drop for ValueCache {
+    fn drop(&mut self) {
+        // 库被卸载时归还占用的全局内存
+        memory::charge_cache(-(self.inner.get_mut().unwrap().bytes as i64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::DataValue;
+
+    #[test]
+    fn test_value_cache() {
+        let node = |v: &str| DataNode::new("k".into(), DataValue::String(v.into()), (0, 0));
+
+        let size = ENTRY_OVERHEAD + 2 + 3;
+        let cache = ValueCache::new(size * 2);
+
+        cache.insert("a", (1, 16), &node("aaa"));
+        cache.insert("b", (1, 32), &node("bbb"));
+        assert!(cache.get("a", (1, 16)).is_some());
+
+        // 位置变化（key 已被重新写入）后不再命中
+        assert!(cache.get("b", (2, 16)).is_none());
+
+        cache.insert("b", (1, 48), &node("bbb"));
+        cache.insert("c", (1, 64), &node("ccc"));
+
+        // 容量只够两项，最久未使用的 a 被淘汰
+        assert!(cache.get("a", (1, 16)).is_none());
+        assert!(cache.get("c", (1, 64)).is_some());
+
+        let report = cache.report("test");
+        assert_eq!(report["entries"], 2);
+        assert_eq!(report["hits"], 2);
+        assert_eq!(report["misses"], 2);
+    }
+}
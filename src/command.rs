@@ -10,7 +10,7 @@ use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     configure::DoreaFileConfig,
-    database::DataBaseManager,
+    database::{CommitTicket, DataBaseManager},
     network::NetPacketState,
     value::DataValue,
 };
@@ -77,6 +77,7 @@ pub enum CommandList {
     SET,
     DELETE,
     CLEAN,
+    HISTORY,
     SELECT,
     SEARCH,
     INFO,
@@ -106,6 +107,7 @@ impl CommandList {
             "SET" => Self::SET,
             "DELETE" => Self::DELETE,
             "CLEAN" => Self::CLEAN,
+            "HISTORY" => Self::HISTORY,
             "SELECT" => Self::SELECT,
             "SEARCH" => Self::SEARCH,
             "INFO" => Self::INFO,
@@ -146,10 +148,11 @@ impl CommandManager {
         // PS：这段代码主要方便后期新增命令
         let mut command_argument_info: HashMap<CommandList, (i16, i16)> = HashMap::new();
 
-        command_argument_info.insert(CommandList::GET, (1, 1));
+        command_argument_info.insert(CommandList::GET, (1, 3));
         command_argument_info.insert(CommandList::SET, (2, -1));
         command_argument_info.insert(CommandList::DELETE, (1, 1));
         command_argument_info.insert(CommandList::CLEAN, (0, 1));
+        command_argument_info.insert(CommandList::HISTORY, (1, 2));
         command_argument_info.insert(CommandList::SELECT, (1, 1));
         command_argument_info.insert(CommandList::SEARCH, (1, -1));
         command_argument_info.insert(CommandList::INFO, (1, 3));
@@ -158,7 +161,7 @@ impl CommandManager {
         command_argument_info.insert(CommandList::EVAL, (1, -1));
         command_argument_info.insert(CommandList::AUTH, (1, 1));
         command_argument_info.insert(CommandList::VALUE, (1, 2));
-        command_argument_info.insert(CommandList::DB, (1, 3));
+        command_argument_info.insert(CommandList::DB, (1, 5));
         command_argument_info.insert(CommandList::DOCS, (0, 1));
         command_argument_info.insert(CommandList::SERVICE, (1, -1));
 
@@ -209,10 +212,42 @@ impl CommandManager {
             );
         }
 
-        // ensure current database is loaded
-        database_manager
-            .ensure_loaded(current, &config.database)
-            .await;
+        let load_timeout = std::time::Duration::from_millis(config.database.load_timeout);
+
+        // 访问当前库的命令需要等待库加载完成（超时返回 LOADING 错误，加载在后台继续）
+        if !matches!(
+            command,
+            CommandList::AUTH
+                | CommandList::PING
+                | CommandList::SELECT
+                | CommandList::DB
+                | CommandList::DOCS
+                | CommandList::SERVICE
+        ) {
+            if let Err(e) = database_manager.wait_loaded(current, load_timeout).await {
+                return (NetPacketState::ERR, e.to_string().as_bytes().to_vec());
+            }
+        }
+
+        // 维护中的库只允许执行 INFO 等管理命令
+        if matches!(
+            command,
+            CommandList::GET
+                | CommandList::SET
+                | CommandList::DELETE
+                | CommandList::CLEAN
+                | CommandList::HISTORY
+                | CommandList::SEARCH
+                | CommandList::EDIT
+                | CommandList::EVAL
+                | CommandList::VALUE
+        ) {
+            if let Some(db_arc) = database_manager.db_list.get(current).map(|v| v.value().clone()) {
+                if let Err(e) = db_arc.read().await.check_readable() {
+                    return (NetPacketState::ERR, e.to_string().as_bytes().to_vec());
+                }
+            }
+        }
 
         // start to command operation
 
@@ -277,13 +312,18 @@ impl CommandManager {
                 }
             }
 
-            // 写锁执行 SET
+            // 写入由该库的写入任务按顺序执行，这里只需要读锁（合并、快照等操作持有写锁时等待）
             let db_arc = database_manager.db_list.get(current).unwrap().clone();
-            let mut db = db_arc.write().await;
+            let db = db_arc.read().await;
+            if expire == 0 {
+                expire = db.default_ttl();
+            }
             let result = db.set(key, data_value, expire).await;
+            let ticket = db.commit_ticket();
+            drop(db);
 
             return match result {
-                Ok(_) => (NetPacketState::OK, vec![]),
+                Ok(_) => commit_reply(ticket).await,
                 Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
             };
         }
@@ -299,7 +339,40 @@ impl CommandManager {
             // 读锁执行 GET
             let db_arc = database_manager.db_list.get(current).unwrap().clone();
             let db = db_arc.read().await;
-            let result = db.meta_data(&key).await;
+
+            // get <key> --at <timestamp>：读取历史版本
+            if slice.len() > 1 {
+                let timestamp = match (slice.get(1).map(|v| v.as_str()), slice.get(2)) {
+                    (Some("--at"), Some(v)) => v.parse::<i64>().ok(),
+                    _ => None,
+                };
+
+                let timestamp = match timestamp {
+                    Some(v) => v,
+                    None => {
+                        return (
+                            NetPacketState::ERR,
+                            "Usage: get <key> [--at <timestamp>]".as_bytes().to_vec(),
+                        )
+                    }
+                };
+
+                return match db.get_at(&key, timestamp).await {
+                    Ok(Some(v)) => (
+                        NetPacketState::OK,
+                        crate::value::value_ser_string(v, value_ser_style)
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+                    Ok(None) => (NetPacketState::ERR, "Data Not Found".as_bytes().to_vec()),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            }
+
+            let result = match db.meta_data(&key).await {
+                Ok(v) => v,
+                Err(e) => return (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+            };
 
             return match result {
                 Some(v) => {
@@ -325,6 +398,55 @@ impl CommandManager {
             };
         }
 
+        if command == CommandList::HISTORY {
+            let key = slice.first().unwrap();
+
+            let limit = match slice.get(1) {
+                Some(v) => match v.parse::<usize>() {
+                    Ok(v) => v,
+                    Err(_) => {
+                        return (
+                            NetPacketState::ERR,
+                            "Usage: history <key> [n]".as_bytes().to_vec(),
+                        )
+                    }
+                },
+                None => 10,
+            };
+
+            let db_arc = database_manager.db_list.get(current).unwrap().clone();
+            let db = db_arc.read().await;
+
+            return match db.history(key, limit).await {
+                Ok(list) if list.is_empty() => {
+                    (NetPacketState::ERR, "Data Not Found".as_bytes().to_vec())
+                }
+                Ok(list) => {
+                    let list: Vec<serde_json::Value> = list
+                        .into_iter()
+                        .map(|v| {
+                            let deleted = v.value == DataValue::None;
+                            serde_json::json!({
+                                "value": crate::value::value_ser_string(v.value, value_ser_style),
+                                "time": v.time_stamp.0,
+                                "expire": v.time_stamp.1,
+                                "deleted": deleted,
+                            })
+                        })
+                        .collect();
+
+                    (
+                        NetPacketState::OK,
+                        serde_json::to_string(&list)
+                            .unwrap_or_else(|_| "[]".into())
+                            .as_bytes()
+                            .to_vec(),
+                    )
+                }
+                Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+            };
+        }
+
         if command == CommandList::DELETE {
             let key = slice.first().unwrap();
 
@@ -333,13 +455,15 @@ impl CommandManager {
                 .add_weight(current.to_string(), 5)
                 .await;
 
-            // 写锁执行 DELETE
+            // 与 SET 相同，只需要读锁
             let db_arc = database_manager.db_list.get(current).unwrap().clone();
-            let mut db = db_arc.write().await;
+            let db = db_arc.read().await;
             let result = db.delete(key.as_ref()).await;
+            let ticket = db.commit_ticket();
+            drop(db);
 
             return match result {
-                Ok(_) => (NetPacketState::OK, vec![]),
+                Ok(_) => commit_reply(ticket).await,
                 Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
             };
         }
@@ -352,11 +476,13 @@ impl CommandManager {
 
             // 写锁执行 CLEAN
             let db_arc = database_manager.db_list.get(current).unwrap().clone();
-            let mut db = db_arc.write().await;
+            let db = db_arc.write().await;
             let result = db.clean().await;
+            let ticket = db.commit_ticket();
+            drop(db);
 
             return match result {
-                Ok(_) => (NetPacketState::OK, vec![]),
+                Ok(_) => commit_reply(ticket).await,
                 Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
             };
         }
@@ -367,12 +493,16 @@ impl CommandManager {
             // 将当前使用的库加入到 DB统计 中（防止被动态卸载）
             crate::server::db_stat_set(*connect_id, db_name.to_string()).await;
 
-            return match database_manager.select_to(db_name).await {
+            return match database_manager.wait_loaded(db_name, load_timeout).await {
                 Ok(_) => {
                     *current = db_name.to_string();
                     (NetPacketState::OK, vec![])
                 }
-                Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                Err(e) => {
+                    // 切换失败，仍然使用原来的库
+                    crate::server::db_stat_set(*connect_id, current.to_string()).await;
+                    (NetPacketState::ERR, e.to_string().as_bytes().to_vec())
+                }
             };
         }
 
@@ -382,6 +512,9 @@ impl CommandManager {
         // max-connect-number 最大连接数
         // server-startup-time 服务器启动时间
         // keys 返回组下所有 Key 信息
+        // cache 当前组的数据缓存统计
+        // memory 全局内存占用统计
+        // eviction 库的自动卸载评估
         // @key 数据内部信息获取
 
         if command == CommandList::INFO {
@@ -412,9 +545,25 @@ impl CommandManager {
 
             if argument == "total-index-number" || argument == "tin" {
                 let temp = crate::database::total_index_number().await;
+                return (NetPacketState::OK, temp.to_string().as_bytes().to_vec());
+            }
+
+            if argument == "eviction" {
+                return (
+                    NetPacketState::OK,
+                    database_manager
+                        .eviction_report()
+                        .await
+                        .to_string()
+                        .as_bytes()
+                        .to_vec(),
+                );
+            }
+
+            if argument == "memory" {
                 return (
                     NetPacketState::OK,
-                    format!("{}/{}", temp.0, temp.1).as_bytes().to_vec(),
+                    crate::memory::report().to_string().as_bytes().to_vec(),
                 );
             }
 
@@ -432,6 +581,13 @@ impl CommandManager {
                 );
             }
 
+            if argument == "cache" {
+                let db_arc = database_manager.db_list.get(current).unwrap().clone();
+                let report = db_arc.read().await.cache_report();
+
+                return (NetPacketState::OK, report.to_string().as_bytes().to_vec());
+            }
+
             if argument == "keys" {
                 let db_arc = database_manager.db_list.get(current).unwrap().clone();
                 let db = db_arc.read().await;
@@ -448,7 +604,10 @@ impl CommandManager {
 
                 let db_arc = database_manager.db_list.get(current).unwrap().clone();
                 let db = db_arc.read().await;
-                let data = db.meta_data(var).await;
+                let data = match db.meta_data(var).await {
+                    Ok(v) => v,
+                    Err(e) => return (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
 
                 if data.is_none() {
                     return (
@@ -489,7 +648,15 @@ impl CommandManager {
                 } else if sub_info == "timestamp" {
                     _result = format!("{:?}", data.timestamp());
                 } else if sub_info == "weight" {
-                    _result = data.weight().to_string();
+                    // 同时返回记录的原始大小与实际占用（压缩后）的大小
+                    let size = crate::record::logical_size(&data);
+                    let stored_size = db.stored_size(var).unwrap_or(size);
+                    _result = serde_json::json!({
+                        "weight": data.weight(),
+                        "size": size,
+                        "stored_size": stored_size,
+                    })
+                    .to_string();
                 }
 
                 return (NetPacketState::OK, _result.as_bytes().to_vec());
@@ -521,7 +688,12 @@ impl CommandManager {
                 let db_arc = database_manager.db_list.get(current).unwrap().clone();
                 let (origin_value, node_timestamp) = {
                     let db = db_arc.read().await;
-                    let node = db.meta_data(key).await;
+                    let node = match db.meta_data(key).await {
+                        Ok(v) => v,
+                        Err(e) => {
+                            return (NetPacketState::ERR, e.to_string().as_bytes().to_vec())
+                        }
+                    };
 
                     if node.is_none() {
                         return (
@@ -749,9 +921,13 @@ impl CommandManager {
                 }
 
                 // 写锁执行写回
-                let mut db = db_arc.write().await;
-                return match db.set(key, _result, expire).await {
-                    Ok(_) => (NetPacketState::OK, vec![]),
+                let db = db_arc.write().await;
+                let result = db.set(key, _result, expire).await;
+                let ticket = db.commit_ticket();
+                drop(db);
+
+                return match result {
+                    Ok(_) => commit_reply(ticket).await,
                     Err(err) => (NetPacketState::ERR, err.to_string().as_bytes().to_vec()),
                 };
             }
@@ -861,10 +1037,110 @@ impl CommandManager {
                     );
                 }
 
+                if !config.database.create_on_select && !database_manager.group_exists(db_name) {
+                    return (
+                        NetPacketState::ERR,
+                        format!("group `{}` not found", db_name).as_bytes().to_vec(),
+                    );
+                }
+
                 return (
                     NetPacketState::OK,
                     format!("@[PRELOAD_DB]:{}", db_name).as_bytes().to_vec(),
                 );
+            } else if operation == "drop" {
+                if slice.len() != 2 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let db_name: &str = slice.get(1).unwrap();
+
+                return match database_manager.drop_group(db_name).await {
+                    Ok(_) => (NetPacketState::OK, vec![]),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            } else if operation == "rename" || operation == "copy" || operation == "fork" {
+                if slice.len() != 3 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let source: &str = slice.get(1).unwrap();
+                let target: &str = slice.get(2).unwrap();
+
+                let result = match operation {
+                    "rename" => database_manager.rename_group(source, target).await,
+                    "copy" => database_manager.clone_group(source, target, false).await,
+                    _ => database_manager.clone_group(source, target, true).await,
+                };
+
+                return match result {
+                    Ok(_) => (NetPacketState::OK, vec![]),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            } else if operation == "config" {
+                if slice.len() < 2 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let db_name: &str = slice.get(1).unwrap();
+                let action = slice.get(2).map(|v| v.as_str()).unwrap_or("get");
+
+                let result = match (action, slice.len()) {
+                    ("get", 2..=4) => database_manager.group_config(db_name).await,
+                    ("set", 5) => {
+                        database_manager
+                            .set_group_config(db_name, &slice[3], &slice[4])
+                            .await
+                    }
+                    _ => {
+                        return (
+                            NetPacketState::ERR,
+                            "Parameter non-specification"
+                                .to_string()
+                                .as_bytes()
+                                .to_vec(),
+                        );
+                    }
+                };
+
+                let config = match result.map(|v| serde_json::to_value(v).unwrap_or_default()) {
+                    Ok(v) => v,
+                    Err(e) => return (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+
+                // get 指定配置项时只返回该项
+                let config = match slice.get(3) {
+                    Some(key) if action == "get" => match config.get(key) {
+                        Some(v) => v.clone(),
+                        None => {
+                            return (
+                                NetPacketState::ERR,
+                                format!("unknown group config `{}`", key).as_bytes().to_vec(),
+                            );
+                        }
+                    },
+                    _ => config,
+                };
+
+                return (NetPacketState::OK, config.to_string().as_bytes().to_vec());
             } else if operation == "list" {
                 let mut list = vec![];
                 for entry in database_manager.db_list.iter() {
@@ -938,6 +1214,126 @@ impl CommandManager {
                     .insert(db_name.to_string(), crate::database::DataBaseState::NORMAL);
 
                 return (NetPacketState::OK, vec![]);
+            } else if operation == "verify" {
+                if slice.len() != 2 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let db_name: &str = slice.get(1).unwrap();
+
+                return match database_manager.verify_group(db_name).await {
+                    Ok(report) => (
+                        NetPacketState::OK,
+                        serde_json::to_string(&report)
+                            .unwrap_or_else(|_| "{}".into())
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            } else if operation == "snapshot" || operation == "restore" {
+                if slice.len() != 3 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                // 路径中包含空格时需要使用引号，解析参数时引号会被保留
+                let unquote = |v: &String| v.trim_matches('"').to_string();
+
+                let result = if operation == "snapshot" {
+                    let db_name = unquote(slice.get(1).unwrap());
+                    let path = std::path::PathBuf::from(unquote(slice.get(2).unwrap()));
+                    database_manager.snapshot_group(&db_name, &path).await
+                } else {
+                    let path = std::path::PathBuf::from(unquote(slice.get(1).unwrap()));
+                    let db_name = unquote(slice.get(2).unwrap());
+                    database_manager.restore_group(&path, &db_name).await
+                };
+
+                return match result {
+                    Ok(manifest) => (
+                        NetPacketState::OK,
+                        serde_json::to_string(&manifest)
+                            .unwrap_or_else(|_| "{}".into())
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            } else if operation == "restore-at" {
+                if slice.len() != 4 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let db_name: &str = slice.get(1).unwrap();
+                let new_name: &str = slice.get(3).unwrap();
+
+                let timestamp = match slice.get(2).unwrap().parse::<i64>() {
+                    Ok(v) => v,
+                    Err(_) => {
+                        return (
+                            NetPacketState::ERR,
+                            "Timestamp must be a unix timestamp".as_bytes().to_vec(),
+                        )
+                    }
+                };
+
+                return match database_manager
+                    .restore_at(db_name, timestamp, new_name)
+                    .await
+                {
+                    Ok(v) => (NetPacketState::OK, v.to_string().as_bytes().to_vec()),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
+            } else if operation == "merge" {
+                if slice.len() != 2 && slice.len() != 3 {
+                    return (
+                        NetPacketState::ERR,
+                        "Parameter non-specification"
+                            .to_string()
+                            .as_bytes()
+                            .to_vec(),
+                    );
+                }
+
+                let db_name: &str = slice.get(1).unwrap();
+
+                // 合并在后台进行，通过 `db merge <name> status` 查看进度
+                let result = match slice.get(2) {
+                    None => database_manager.merge_group(db_name).await,
+                    Some(v) if v == "status" => database_manager.merge_status(db_name).await,
+                    Some(_) => {
+                        return (
+                            NetPacketState::ERR,
+                            "Unknown operation.".as_bytes().to_vec(),
+                        )
+                    }
+                };
+
+                return match result {
+                    Ok(progress) => (
+                        NetPacketState::OK,
+                        progress.report(db_name).to_string().as_bytes().to_vec(),
+                    ),
+                    Err(e) => (NetPacketState::ERR, e.to_string().as_bytes().to_vec()),
+                };
             } else if operation == "status" {
                 let mut result = HashMap::new();
                 if slice.len() == 1 {
@@ -958,6 +1354,20 @@ impl CommandManager {
                                 "state": state.to_string(),
                                 "weight": elis.get(name),
                                 "index_num": db_guard.size(),
+                                "memory": db_guard.memory_report(),
+                                "eviction": db_guard.eviction_report(),
+                                "config": db_guard.config(),
+                                "storage": db_guard.storage_kind(),
+                            }),
+                        );
+                    }
+
+                    for entry in database_manager.loading.iter() {
+                        result.insert(
+                            entry.key().to_string(),
+                            serde_json::json!({
+                                "state": crate::database::DataBaseState::LOADING.to_string(),
+                                "progress": entry.value().report(),
                             }),
                         );
                     }
@@ -1043,18 +1453,21 @@ impl CommandManager {
 
             if operation == "account" || operation == "acc" {
                 // 确保 system 库已加载
-                database_manager
-                    .ensure_loaded("system", &config.database)
-                    .await;
+                if let Err(e) = database_manager.wait_loaded("system", load_timeout).await {
+                    return (NetPacketState::ERR, e.to_string().as_bytes().to_vec());
+                }
 
                 let system_db = database_manager.db_list.get("system").unwrap().clone();
 
                 // 读锁获取账户数据
                 let acc_val = {
                     let db = system_db.read().await;
-                    db.get("service@accounts")
-                        .await
-                        .unwrap_or_else(|| DataValue::Dict(HashMap::new()))
+                    match db.get("service@accounts").await {
+                        Ok(v) => v.unwrap_or_else(|| DataValue::Dict(HashMap::new())),
+                        Err(e) => {
+                            return (NetPacketState::ERR, e.to_string().as_bytes().to_vec())
+                        }
+                    }
                 };
 
                 if slice.len() <= 1 {
@@ -1102,7 +1515,17 @@ impl CommandManager {
                         "db@unload",
                         "db@lock",
                         "db@unlock",
-                        "db@preload"
+                        "db@preload",
+                        "db@drop",
+                        "db@rename",
+                        "db@config",
+                        "db@snapshot",
+                        "db@restore",
+                        "db@restore-at",
+                        "db@merge",
+                        "db@verify",
+                        "db@copy",
+                        "db@fork"
                     ]);
                     let de_cls_cmd: &str = &de_cls_cmd.to_string();
 
@@ -1130,13 +1553,16 @@ impl CommandManager {
 
                     let checker = {
                         let db = system_db.read().await;
-                        db.get("service@acc-checker")
-                            .await
-                            .unwrap_or(DataValue::None)
+                        match db.get("service@acc-checker").await {
+                            Ok(v) => v.unwrap_or(DataValue::None),
+                            Err(e) => {
+                                return (NetPacketState::ERR, e.to_string().as_bytes().to_vec())
+                            }
+                        }
                     };
 
                     if checker == DataValue::None {
-                        let mut db = system_db.write().await;
+                        let db = system_db.write().await;
                         db.set(
                             "service@acc-checker",
                             DataValue::String(crate::tool::rand_str()),
@@ -1151,7 +1577,7 @@ impl CommandManager {
                     acc_dict.insert(username.to_string(), DataValue::Dict(temp_dict.clone()));
 
                     // 写锁执行写回
-                    let mut db = system_db.write().await;
+                    let db = system_db.write().await;
                     let res = db.set("service@accounts", DataValue::Dict(acc_dict), 0).await;
 
                     if res.is_ok() {
@@ -1223,7 +1649,7 @@ impl CommandManager {
                     }
 
                     // 写锁执行写回
-                    let mut db = system_db.write().await;
+                    let db = system_db.write().await;
                     let res = db.set("service@accounts", DataValue::Dict(v_accs), 0).await;
 
                     if res.is_ok() {
@@ -1278,6 +1704,17 @@ impl CommandManager {
     }
 }
 
+/// 等待写入满足持久化策略后再返回 OK
+async fn commit_reply(ticket: CommitTicket) -> (NetPacketState, Vec<u8>) {
+    match ticket.wait().await {
+        Ok(_) => (NetPacketState::OK, vec![]),
+        Err(e) => (
+            NetPacketState::ERR,
+            format!("durability sync failed: {}", e).as_bytes().to_vec(),
+        ),
+    }
+}
+
 mod edit_operation {
 
     use crate::value::DataValue;
@@ -2,6 +2,7 @@ use crate::Result;
 
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fs,
     path::{Path, PathBuf},
 };
@@ -23,7 +24,334 @@ pub struct ConnectionConfig {
 pub struct DataBaseConfig {
     pub(crate) default_group: String,
     pub(crate) pre_load_group: Vec<String>,
-    pub(crate) max_index_number: u32,
+    /// 切换到不存在的库时自动创建，关闭后返回错误
+    #[serde(default = "default_create_on_select")]
+    pub(crate) create_on_select: bool,
+    /// 索引与数据缓存可以使用的内存上限（字节），单个库最多使用其中的四分之一
+    #[serde(default = "default_max_memory")]
+    pub(crate) max_memory: u64,
+    /// 不会被自动卸载的库
+    #[serde(default)]
+    pub(crate) pinned_groups: Vec<String>,
+    /// 库权重的半衰期（秒），为 0 时权重不衰减
+    #[serde(default = "default_weight_half_life")]
+    pub(crate) weight_half_life: u64,
+    /// 命令等待库加载完成的最长时间（毫秒），超时返回 LOADING 错误
+    #[serde(default = "default_load_timeout")]
+    pub(crate) load_timeout: u64,
+    #[serde(default)]
+    pub(crate) durability: Durability,
+    /// 库默认的存储方式，可以通过 `storage` 为单个库指定
+    #[serde(default)]
+    pub(crate) default_storage: StorageKind,
+    /// 数据文件加密密钥所在的文件（相对于工作目录），环境变量 `DOREA_ENCRYPTION_KEY` 优先
+    #[serde(default)]
+    pub(crate) encryption_key_file: Option<String>,
+    /// 无效数据（被覆盖、删除、过期的记录）占比达到该值时自动合并
+    #[serde(default = "default_merge_dead_ratio")]
+    pub(crate) merge_dead_ratio: f64,
+    /// 无效数据至少达到该字节数才会自动合并，避免小库频繁合并
+    #[serde(default = "default_merge_min_dead_size")]
+    pub(crate) merge_min_dead_size: u64,
+    /// 使用内存映射读取归档文件（仅 unix，其他平台仍使用按位置读取）
+    #[serde(default)]
+    pub(crate) mmap_archives: bool,
+    /// 所有库的数据缓存共用的内存上限（字节）
+    #[serde(default = "default_max_cache_memory")]
+    pub(crate) max_cache_memory: u64,
+    /// 每个库保留的历史版本数量（库名 -> 数量），未配置的库不保留历史，合并时同样会保留这些版本
+    #[serde(default)]
+    pub(crate) history_depth: HashMap<String, usize>,
+    /// 每个库的数据缓存容量（库名 -> 字节数），未配置的库不缓存
+    #[serde(default)]
+    pub(crate) value_cache: HashMap<String, u64>,
+    /// 每个库达到内存上限时的 key 淘汰策略（库名 -> 策略），未配置的库不淘汰
+    #[serde(default)]
+    pub(crate) eviction_policy: HashMap<String, EvictionPolicy>,
+    /// 每个库的存储方式（库名 -> 存储方式），未配置的库使用 `default_storage`
+    #[serde(default)]
+    pub(crate) storage: HashMap<String, StorageKind>,
+}
+
+impl DataBaseConfig {
+    /// 某个库使用的存储方式
+    pub(crate) fn storage_kind(&self, group: &str) -> StorageKind {
+        self.storage.get(group).copied().unwrap_or(self.default_storage)
+    }
+}
+
+fn default_create_on_select() -> bool {
+    true
+}
+
+fn default_max_memory() -> u64 {
+    1024 * 1024 * 256
+}
+
+fn default_weight_half_life() -> u64 {
+    60 * 60
+}
+
+fn default_load_timeout() -> u64 {
+    3000
+}
+
+fn default_merge_dead_ratio() -> f64 {
+    0.5
+}
+
+fn default_merge_min_dead_size() -> u64 {
+    1024 * 1024 * 16
+}
+
+fn default_max_cache_memory() -> u64 {
+    1024 * 1024 * 64
+}
+
+/// 写入持久化策略（active.db 的 fsync 时机）
+///
+/// - `none`: 不主动 fsync，由操作系统决定何时落盘
+/// - `every_write`: 每次写入后 fsync，返回前数据已落盘
+/// - `interval(ms)`: 每隔 ms 毫秒 fsync 一次，期间到达的写入共享同一次 fsync
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum Durability {
+    #[default]
+    None,
+    EveryWrite,
+    Interval(u64),
+}
+
+impl TryFrom<String> for Durability {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        let value = value.trim().to_lowercase();
+
+        match value.as_str() {
+            "none" => return Ok(Self::None),
+            "every_write" => return Ok(Self::EveryWrite),
+            _ => {}
+        }
+
+        let ms = value
+            .strip_prefix("interval(")
+            .and_then(|v| v.strip_suffix(')'))
+            .and_then(|v| v.trim().parse::<u64>().ok());
+
+        match ms {
+            Some(ms) if ms > 0 => Ok(Self::Interval(ms)),
+            _ => Err(format!(
+                "unknown durability `{}`, expected none | every_write | interval(ms)",
+                value
+            )),
+        }
+    }
+}
+
+impl From<Durability> for String {
+    fn from(value: Durability) -> Self {
+        match value {
+            Durability::None => String::from("none"),
+            Durability::EveryWrite => String::from("every_write"),
+            Durability::Interval(ms) => format!("interval({})", ms),
+        }
+    }
+}
+
+/// 库的存储方式
+///
+/// - `log`: 记录追加写入库目录中的数据文件
+/// - `memory`: 记录只保存在内存中，不读写磁盘，库被卸载或重启后数据丢失
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Log,
+    Memory,
+}
+
+/// 库达到内存上限时写入新 key 的处理方式
+///
+/// - `noeviction`: 拒绝写入
+/// - `allkeys-lru`: 淘汰最久未访问的 key
+/// - `allkeys-lfu`: 淘汰访问频率最低的 key
+/// - `volatile-ttl`: 在设置了过期时间的 key 中淘汰最先过期的
+/// - `volatile-lru`: 在设置了过期时间的 key 中淘汰最久未访问的
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum EvictionPolicy {
+    #[default]
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    VolatileTtl,
+    VolatileLru,
+}
+
+impl TryFrom<String> for EvictionPolicy {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "noeviction" => Ok(Self::NoEviction),
+            "allkeys-lru" => Ok(Self::AllKeysLru),
+            "allkeys-lfu" => Ok(Self::AllKeysLfu),
+            "volatile-ttl" => Ok(Self::VolatileTtl),
+            "volatile-lru" => Ok(Self::VolatileLru),
+            v => Err(format!(
+                "unknown eviction policy `{}`, expected noeviction | allkeys-lru | allkeys-lfu | volatile-ttl | volatile-lru",
+                v
+            )),
+        }
+    }
+}
+
+impl From<EvictionPolicy> for String {
+    fn from(value: EvictionPolicy) -> Self {
+        String::from(match value {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+        })
+    }
+}
+
+/// 库的读写模式
+///
+/// - `normal`: 正常读写
+/// - `read-only`: 拒绝写入
+/// - `maintenance`: 维护中，拒绝读取和写入（`info`、`db` 等管理命令不受影响）
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum GroupMode {
+    #[default]
+    Normal,
+    ReadOnly,
+    Maintenance,
+}
+
+impl TryFrom<String> for GroupMode {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "normal" => Ok(Self::Normal),
+            "read-only" => Ok(Self::ReadOnly),
+            "maintenance" => Ok(Self::Maintenance),
+            v => Err(format!(
+                "unknown group mode `{}`, expected normal | read-only | maintenance",
+                v
+            )),
+        }
+    }
+}
+
+impl From<GroupMode> for String {
+    fn from(value: GroupMode) -> Self {
+        String::from(match value {
+            GroupMode::Normal => "normal",
+            GroupMode::ReadOnly => "read-only",
+            GroupMode::Maintenance => "maintenance",
+        })
+    }
+}
+
+/// 记录的压缩算法
+///
+/// - `none`: 不压缩
+/// - `zstd`: 压缩率较高
+/// - `lz4`: 压缩与解压速度较快
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl TryFrom<String> for Compression {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "zstd" => Ok(Self::Zstd),
+            "lz4" => Ok(Self::Lz4),
+            v => Err(format!(
+                "unknown compression `{}`, expected none | zstd | lz4",
+                v
+            )),
+        }
+    }
+}
+
+impl From<Compression> for String {
+    fn from(value: Compression) -> Self {
+        String::from(match value {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Lz4 => "lz4",
+        })
+    }
+}
+
+/// 单个库的配置，保存在库目录中（与 `state.json` 同级），通过 `db config` 修改
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// 本库可以使用的内存上限（字节），为 0 时使用 `max_memory` 的四分之一
+    pub(crate) max_memory: u64,
+    /// `set` 没有指定过期时间时使用的过期时间（秒），为 0 时不过期
+    pub(crate) default_ttl: u64,
+    pub(crate) mode: GroupMode,
+    /// 覆盖全局的 `durability`，下次加载时生效
+    pub(crate) durability: Option<Durability>,
+    /// 新写入记录的压缩算法，已有的记录保持原样
+    pub(crate) compression: Compression,
+    /// 编码后超过该字节数的记录才压缩，为 0 时使用默认的 1024 字节
+    pub(crate) compress_threshold: u64,
+}
+
+impl GroupConfig {
+    /// 修改一项配置，`durability` 设置为 `default` 时使用全局配置
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let parse_u64 = |v: &str| {
+            v.parse::<u64>()
+                .map_err(|_| anyhow::anyhow!("`{}` expects a number, got `{}`", key, v))
+        };
+
+        match key {
+            "max_memory" => self.max_memory = parse_u64(value)?,
+            "default_ttl" => self.default_ttl = parse_u64(value)?,
+            "mode" => {
+                self.mode = GroupMode::try_from(value.to_string()).map_err(anyhow::Error::msg)?
+            }
+            "compression" => {
+                self.compression =
+                    Compression::try_from(value.to_string()).map_err(anyhow::Error::msg)?
+            }
+            "compress_threshold" => self.compress_threshold = parse_u64(value)?,
+            "durability" => {
+                self.durability = if value.eq_ignore_ascii_case("default") {
+                    None
+                } else {
+                    Some(Durability::try_from(value.to_string()).map_err(anyhow::Error::msg)?)
+                }
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "unknown group config `{}`, expected max_memory | default_ttl | mode | durability | compression | compress_threshold",
+                    key
+                ))
+            }
+        }
+
+        Ok(())
+    }
 }
 
 // HTTP Restful Service 配置
@@ -48,11 +376,6 @@ pub(crate) fn load_config(path: &Path) -> Result<DoreaFileConfig> {
 
     let mut result = toml::from_str::<DoreaFileConfig>(&value)?;
 
-    // 不能大于这个峰值 2048000 条数据
-    if result.database.max_index_number >= 2048000 {
-        result.database.max_index_number = 2048000;
-    }
-
     // pre_load 最多为 4 个（保证索引数不溢出）
     if result.database.pre_load_group.len() > 4 {
         let mut temp = vec![];
@@ -97,7 +420,22 @@ fn init_config(path: PathBuf) -> Result<()> {
         database: DataBaseConfig {
             default_group: String::from("default"),
             pre_load_group: vec![String::from("default"), String::from("system")],
-            max_index_number: 102400,
+            create_on_select: default_create_on_select(),
+            max_memory: default_max_memory(),
+            pinned_groups: vec![],
+            weight_half_life: default_weight_half_life(),
+            load_timeout: default_load_timeout(),
+            durability: Durability::None,
+            default_storage: StorageKind::Log,
+            encryption_key_file: None,
+            merge_dead_ratio: default_merge_dead_ratio(),
+            merge_min_dead_size: default_merge_min_dead_size(),
+            mmap_archives: false,
+            max_cache_memory: default_max_cache_memory(),
+            history_depth: HashMap::new(),
+            value_cache: HashMap::new(),
+            eviction_policy: HashMap::new(),
+            storage: HashMap::new(),
         },
     };
 
@@ -121,3 +459,56 @@ fn init_config(path: PathBuf) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_config_set() {
+        let mut config = GroupConfig::default();
+
+        config.set("max_memory", "4096").unwrap();
+        config.set("default_ttl", "60").unwrap();
+        config.set("mode", "read-only").unwrap();
+        config.set("compression", "zstd").unwrap();
+        config.set("compress_threshold", "512").unwrap();
+        config.set("durability", "interval(100)").unwrap();
+
+        assert_eq!(
+            config,
+            GroupConfig {
+                max_memory: 4096,
+                default_ttl: 60,
+                mode: GroupMode::ReadOnly,
+                durability: Some(Durability::Interval(100)),
+                compression: Compression::Zstd,
+                compress_threshold: 512,
+            }
+        );
+
+        // `default` 恢复使用全局配置
+        config.set("durability", "default").unwrap();
+        assert_eq!(config.durability, None);
+
+        // 无效的值不修改原有配置
+        let origin = config.clone();
+        for (key, value) in [
+            ("max_memory", "-1"),
+            ("default_ttl", "1h"),
+            ("compress_threshold", ""),
+            ("mode", "readonly"),
+            ("compression", "gzip"),
+            ("durability", "interval(0)"),
+            ("durability", "always"),
+            ("history_depth", "3"),
+        ] {
+            assert!(config.set(key, value).is_err(), "{} = {}", key, value);
+        }
+        assert_eq!(config, origin);
+        assert_eq!(
+            config.set("default_ttl", "1h").unwrap_err().to_string(),
+            "`default_ttl` expects a number, got `1h`"
+        );
+    }
+}
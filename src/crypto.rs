@@ -0,0 +1,229 @@
+//! 数据文件加密
+//!
+//! 配置密钥后，记录的数据（压缩之后）使用 ChaCha20-Poly1305 加密，每条记录使用随机 nonce：
+//!
+//! ```text
+//! | KEY_ID u32 | NONCE [u8; 12] | CIPHERTEXT | TAG [u8; 16] |
+//! ```
+//!
+//! 密钥来自环境变量 `DOREA_ENCRYPTION_KEY` 或 `encryption_key_file` 指定的文件，
+//! 每个密钥为 base64 编码的 32 字节，可以配置多个：第一个用于加密新数据，其余的只用于读取之前写入的数据。
+//! 合并时使用第一个密钥重新加密其他密钥写入的记录，所有库合并完成后即可移除旧密钥。
+//!
+//! 密钥的校验值写入数据文件头，加载时据此发现密钥配置错误，而不是把无法解密的记录当作损坏数据；
+//! 密钥编号为校验值的前 4 字节。
+
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use anyhow::anyhow;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+
+use crate::configure::DataBaseConfig;
+
+pub(crate) const KEY_ENV: &str = "DOREA_ENCRYPTION_KEY";
+pub(crate) const KEY_CHECK_SIZE: usize = 16;
+
+const KEY_SIZE: usize = 32;
+const NONCE_SIZE: usize = 12;
+const KEY_CHECK_AAD: &[u8] = b"dorea key check";
+
+static KEYRING: Lazy<RwLock<Arc<Keyring>>> = Lazy::new(Default::default);
+
+/// 当前使用的密钥（未配置时为空）
+pub(crate) fn keyring() -> Arc<Keyring> {
+    KEYRING.read().unwrap().clone()
+}
+
+/// 读取配置的密钥，环境变量优先于 `encryption_key_file`（相对路径基于工作目录）
+pub(crate) fn init(config: &DataBaseConfig, root: &Path) -> crate::Result<()> {
+    let text = match (std::env::var(KEY_ENV), &config.encryption_key_file) {
+        (Ok(v), _) => v,
+        (Err(_), Some(path)) => {
+            let path = root.join(path);
+            std::fs::read_to_string(&path).map_err(|e| anyhow!("{:?}: {}", path, e))?
+        }
+        (Err(_), None) => String::new(),
+    };
+
+    let keyring = Keyring::parse(&text)?;
+
+    if let Some(key) = keyring.current() {
+        log::info!(
+            "data file encryption enabled [key {:08x}, {} keys].",
+            key.id(),
+            keyring.keys.len()
+        );
+    }
+
+    *KEYRING.write().unwrap() = Arc::new(keyring);
+
+    Ok(())
+}
+
+pub(crate) struct Key {
+    check: [u8; KEY_CHECK_SIZE],
+    cipher: ChaCha20Poly1305,
+}
+
+impl std::fmt::Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Key").field("id", &self.id()).finish_non_exhaustive()
+    }
+}
+
+impl Key {
+    pub(crate) fn new(bytes: &[u8]) -> crate::Result<Self> {
+        if bytes.len() != KEY_SIZE {
+            return Err(anyhow!(
+                "encryption key must be {} bytes, got {}",
+                KEY_SIZE,
+                bytes.len()
+            ));
+        }
+
+        let cipher = ChaCha20Poly1305::new_from_slice(bytes).map_err(|e| anyhow!(e.to_string()))?;
+
+        // 校验值：以全 0 nonce 加密空数据得到的认证标签，不会泄露密钥本身
+        let tag = cipher
+            .encrypt(
+                &Nonce::default(),
+                Payload {
+                    msg: b"",
+                    aad: KEY_CHECK_AAD,
+                },
+            )
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(Self {
+            check: tag.as_slice().try_into()?,
+            cipher,
+        })
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        u32::from_le_bytes(self.check[0..4].try_into().unwrap())
+    }
+
+    pub(crate) fn check(&self) -> &[u8; KEY_CHECK_SIZE] {
+        &self.check
+    }
+
+    /// 加密数据，结果为 `| KEY_ID | NONCE | CIPHERTEXT | TAG |`
+    pub(crate) fn seal(&self, data: &[u8]) -> Vec<u8> {
+        let mut nonce = [0_u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .expect("chacha20poly1305 encryption failed");
+
+        let mut buf = Vec::with_capacity(4 + NONCE_SIZE + sealed.len());
+        buf.extend_from_slice(&self.id().to_le_bytes());
+        buf.extend_from_slice(&nonce);
+        buf.extend_from_slice(&sealed);
+        buf
+    }
+
+    fn open(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        let (nonce, sealed) = data.split_at(NONCE_SIZE);
+
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow!("record decryption failed with key {:08x}", self.id()))
+    }
+}
+
+/// 配置的全部密钥，第一个用于加密
+#[derive(Debug, Default)]
+pub(crate) struct Keyring {
+    keys: Vec<Key>,
+}
+
+impl Keyring {
+    /// 解析以空白或逗号分隔的 base64 密钥
+    pub(crate) fn parse(text: &str) -> crate::Result<Self> {
+        let mut keys: Vec<Key> = vec![];
+
+        for (i, item) in text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|v| !v.is_empty())
+            .enumerate()
+        {
+            let bytes = base64::decode(item)
+                .map_err(|e| anyhow!("encryption key #{} is not valid base64: {}", i + 1, e))?;
+            let key = Key::new(&bytes).map_err(|e| anyhow!("encryption key #{}: {}", i + 1, e))?;
+
+            if keys.iter().any(|v| v.id() == key.id()) {
+                return Err(anyhow!("encryption key #{} is configured twice", i + 1));
+            }
+            keys.push(key);
+        }
+
+        Ok(Self { keys })
+    }
+
+    pub(crate) fn current(&self) -> Option<&Key> {
+        self.keys.first()
+    }
+
+    /// 按数据文件头中的校验值查找密钥
+    pub(crate) fn find_check(&self, check: &[u8]) -> Option<&Key> {
+        self.keys.iter().find(|v| v.check.as_slice() == check)
+    }
+
+    /// 解密 [`Key::seal`] 的结果
+    pub(crate) fn open(&self, data: &[u8]) -> crate::Result<Vec<u8>> {
+        if data.len() < 4 + NONCE_SIZE {
+            return Err(anyhow!("encrypted payload truncated"));
+        }
+
+        let id = Self::key_id(data);
+        let key = self.keys.iter().find(|v| v.id() == id).ok_or_else(|| {
+            anyhow!("record is encrypted with key {:08x}, which is not configured", id)
+        })?;
+
+        key.open(&data[4..])
+    }
+
+    /// 加密数据使用的密钥编号
+    pub(crate) fn key_id(data: &[u8]) -> u32 {
+        u32::from_le_bytes(data[0..4].try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring() {
+        let old = base64::encode([1_u8; KEY_SIZE]);
+        let new = base64::encode([2_u8; KEY_SIZE]);
+
+        let before = Keyring::parse(&old).unwrap();
+        let sealed = before.current().unwrap().seal(b"dorea");
+        assert_eq!(before.open(&sealed).unwrap(), b"dorea");
+
+        // 轮换后旧密钥只用于解密
+        let after = Keyring::parse(&format!("{}\n{}", new, old)).unwrap();
+        assert_ne!(after.current().unwrap().id(), Keyring::key_id(&sealed));
+        assert_eq!(after.open(&sealed).unwrap(), b"dorea");
+
+        let wrong = Keyring::parse(&new).unwrap();
+        assert!(wrong.open(&sealed).is_err());
+        assert!(wrong.find_check(before.current().unwrap().check()).is_none());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(before.open(&tampered).is_err());
+
+        assert!(Keyring::parse("c2hvcnQ=").is_err());
+        assert!(Keyring::parse(&format!("{},{}", old, old)).is_err());
+        assert!(Keyring::parse(" \n").unwrap().current().is_none());
+    }
+}
@@ -1,34 +1,61 @@
 use std::fs::{self, rename};
-use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::Write;
 use std::path::Path;
-use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::Arc;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+use std::collections::{BTreeSet, BinaryHeap, HashSet, VecDeque};
 use std::{collections::HashMap, path::PathBuf};
 
 use log::info;
-use nom::AsBytes;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 
-use bytes::{BufMut, BytesMut};
 use dashmap::DashMap;
 use serde_json::json;
-use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
-use tokio::sync::{Mutex, RwLock};
-
-use crate::configure::{self, DataBaseConfig, DoreaFileConfig};
+use tokio::sync::{mpsc, oneshot, watch, Mutex, RwLock};
+
+use crate::cache::ValueCache;
+use crate::configure::{
+    self, DataBaseConfig, DoreaFileConfig, Durability, EvictionPolicy, GroupConfig, GroupMode, StorageKind,
+};
+use crate::hint::{self, HintEntry};
+use crate::record::{self, Codec, RecordReader};
+use crate::snapshot::{self, Manifest};
+use crate::storage::log::{
+    archive_ids, replay_until, unshare_archives, verify_files, LogStorage, MergeOutput, MergePlan, VerifyReport,
+    SNAPSHOT_STAGING_PREFIX,
+};
+use crate::storage::{MemoryStorage, StorageBackend};
 use crate::value::DataValue;
 use crate::Result;
 
 use anyhow::anyhow;
 
-// 单个数据库占全系统可用
-const INDEX_PROPORTION_FOR_DB: u16 = 4;
+// 单个数据库最多使用全系统内存上限的 1/4
+const MEMORY_PROPORTION_FOR_DB: u64 = 4;
 
 // 全局索引计数（原子操作，替代原来的 Mutex<TotalInfo>）
-static TOTAL_INDEX_NUMBER: AtomicU32 = AtomicU32::new(0);
-static MAX_INDEX_NUMBER: AtomicU32 = AtomicU32::new(u32::MAX);
+pub(crate) static TOTAL_INDEX_NUMBER: AtomicU32 = AtomicU32::new(0);
+
+// 每个索引项除 key 内容以外的内存开销估算（IndexInfo、String 头部与哈希表槽位）
+const INDEX_ENTRY_OVERHEAD: u64 = (std::mem::size_of::<IndexInfo>() + std::mem::size_of::<String>() + 16) as u64;
+
+// 每个历史版本的内存开销估算
+const VERSION_OVERHEAD: u64 = std::mem::size_of::<Version>() as u64;
+
+pub(crate) fn index_entry_size(key: &str) -> u64 {
+    INDEX_ENTRY_OVERHEAD + key.len() as u64
+}
+
+// 淘汰时至少释放本库内存上限的 1/64，避免库满之后每次写入都扫描一遍索引
+const EVICTION_BATCH_PROPORTION: u64 = 64;
+
+// LFU 访问计数每隔该时间（秒）未被访问减半
+const LFU_DECAY_TIME: i64 = 600;
+
+// 库目录中保存单库配置的文件
+pub(crate) const GROUP_CONFIG_FILE: &str = "config.json";
 
 /// 数据管理结构
 /// db_list 数据库列表（当前系统已加载的所有数据）
@@ -39,25 +66,37 @@ pub struct DataBaseManager {
     pub(crate) db_list: DashMap<String, Arc<RwLock<DataBase>>>,
     pub(crate) location: PathBuf,
     pub(crate) config: DoreaFileConfig,
-    pub(crate) eli_queue: Mutex<HashMap<String, isize>>,
+    pub(crate) eli_queue: Mutex<HashMap<String, f64>>,
+    /// 正在后台加载的库
+    pub(crate) loading: DashMap<String, Arc<LoadTask>>,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct DataBase {
     name: String,
-    index: HashMap<String, IndexInfo>,
     timestamp: i64,
     location: PathBuf,
-    file: DataFile,
+    /// 索引等共享状态，读取时不需要经过写入任务
+    state: Arc<GroupState>,
+    /// 写入任务的请求队列
+    writer: mpsc::Sender<WriteOp>,
+    /// 达到内存上限时的 key 淘汰策略
+    eviction: EvictionPolicy,
+    /// 单库配置（配额、默认过期时间、读写模式）
+    config: GroupConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DataNode {
-    crc: u32,
-    key: String,
+    pub(crate) crc: u32,
+    pub(crate) key: String,
     pub(crate) value: DataValue,
-    time_stamp: (i64, u64),
+    pub(crate) time_stamp: (i64, u64),
+    /// 校验码计算方式：0 为旧版本（基于 `value.to_string()`），1 为规范化 JSON，
+    /// 2 为二进制记录（校验码覆盖整条记录，在解码时已经检查）
+    #[serde(default)]
+    crc_version: u8,
 }
 
 pub static DB_STATE: Lazy<Mutex<HashMap<String, DataBaseState>>> =
@@ -85,11 +124,56 @@ impl std::fmt::Display for DataBaseState {
 
 pub const CASTAGNOLI: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISCSI);
 
+// JSON 格式记录使用的校验码版本
+const CRC_VERSION: u8 = 1;
+
+// 二进制格式记录：校验码由 `record` 模块在记录层计算与检查
+const CRC_RECORD: u8 = 2;
+
+/// 计算数据值的校验码
+///
+/// 先转换为 `serde_json::Value`（字典键有序）再序列化，保证同一个值每次得到相同的字节。
+pub(crate) fn value_checksum(value: &DataValue) -> u32 {
+    let canonical = serde_json::to_value(value)
+        .and_then(|v| serde_json::to_vec(&v))
+        .unwrap_or_default();
+
+    CASTAGNOLI.checksum(&canonical)
+}
+
+/// 记录是否已过期（`expire` 为 0 表示永不过期）
+pub(crate) fn is_expired(time_stamp: (i64, u64), now: i64) -> bool {
+    matches!(expire_deadline(time_stamp), Some(deadline) if deadline < now)
+}
+
+/// 记录的到期时间，早于当前时间即为过期
+pub(crate) fn expire_deadline(time_stamp: (i64, u64)) -> Option<i64> {
+    match time_stamp.1 {
+        0 => None,
+        v => Some(time_stamp.0.saturating_add(v.min(i64::MAX as u64) as i64)),
+    }
+}
+
+fn contains_dict(value: &DataValue) -> bool {
+    match value {
+        DataValue::Dict(_) => true,
+        DataValue::List(l) => l.iter().any(contains_dict),
+        DataValue::Tuple(t) => contains_dict(&t.0) || contains_dict(&t.1),
+        _ => false,
+    }
+}
+
 impl DataBaseManager {
     pub async fn new(location: PathBuf) -> Self {
         let config = configure::load_config(&location).unwrap();
 
-        MAX_INDEX_NUMBER.store(config.database.max_index_number, Ordering::Relaxed);
+        crate::memory::set_max(config.database.max_memory);
+        crate::cache::set_max_memory(config.database.max_cache_memory);
+
+        // 密钥无效时无法读写加密的数据，不继续启动
+        if let Err(e) = crate::crypto::init(&config.database, &location) {
+            panic!("encryption key load failed: {}", e);
+        }
 
         let (db_list, eli_que) = DataBaseManager::load_database(&config, location.clone()).await;
 
@@ -98,93 +182,144 @@ impl DataBaseManager {
             location: location.clone(),
             config,
             eli_queue: Mutex::new(eli_que),
+            loading: DashMap::new(),
         }
     }
 
-    /// 确保指定数据库已加载
-    pub async fn ensure_loaded(&self, name: &str, db_config: &DataBaseConfig) {
-        if self.db_list.contains_key(name) {
-            return;
-        }
-
-        let state = DataBase::state(name.to_string(), self.location.clone())
-            .await
-            .unwrap_or(StateInfo {
-                index_number: 0,
-                init_version: crate::DOREA_VERSION.to_string(),
-                update_time: chrono::Local::now().timestamp(),
-            });
+    /// 库是否存在（已加载、数据目录存在或者配置为内存存储）
+    pub fn group_exists(&self, name: &str) -> bool {
+        self.db_list.contains_key(name)
+            || self.location.join("storage").join(name).is_dir()
+            || self.config.database.storage_kind(name) == StorageKind::Memory
+    }
 
-        if self.check_eli_db(state.index_number as u64).await.is_err() {
-            log::error!("eviction check failed when loading database '{}'", name);
+    /// 加载库并等待完成，库正在后台加载时等待该次加载
+    pub async fn select_to(&self, name: &str) -> Result<()> {
+        match self.register_load(name)? {
+            LoadTicket::Loaded => Ok(()),
+            LoadTicket::Pending(task) => task.wait().await,
+            LoadTicket::Started(task) => {
+                self.load_group(name, &task).await;
+                task.wait().await
+            }
         }
+    }
 
-        let db = DataBase::init(
-            name.to_string(),
-            self.location.clone().join("storage"),
-            db_config.clone(),
-        )
-        .await;
+    /// 在后台加载库，已经加载时返回 None
+    pub fn start_loading(self: &Arc<Self>, name: &str) -> Result<Option<Arc<LoadTask>>> {
+        Ok(match self.register_load(name)? {
+            LoadTicket::Loaded => None,
+            LoadTicket::Pending(task) => Some(task),
+            LoadTicket::Started(task) => {
+                let manager = self.clone();
+                let name = name.to_string();
+                let started = task.clone();
+                tokio::spawn(async move { manager.load_group(&name, &started).await });
+                Some(task)
+            }
+        })
+    }
 
-        self.db_list
-            .insert(name.to_string(), Arc::new(RwLock::new(db)));
-        self.eli_queue
-            .lock()
-            .await
-            .insert(name.to_string(), 1);
+    /// 等待库加载完成，`timeout` 内没有完成时返回 LOADING 错误，加载继续在后台进行
+    pub async fn wait_loaded(self: &Arc<Self>, name: &str, timeout: std::time::Duration) -> Result<()> {
+        let task = match self.start_loading(name)? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        match tokio::time::timeout(timeout, task.wait()).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!(
+                "LOADING group `{}` is loading [{}/{} bytes]",
+                name,
+                task.progress.scanned_bytes.load(Ordering::Relaxed),
+                task.progress.total_bytes.load(Ordering::Relaxed),
+            )),
+        }
     }
 
-    // 切换数据库
-    pub async fn select_to(&self, name: &str) -> Result<()> {
+    fn register_load(&self, name: &str) -> Result<LoadTicket> {
         if self.db_list.contains_key(name) {
-            return Ok(());
+            return Ok(LoadTicket::Loaded);
         }
 
-        let state = DataBase::state(name.to_string(), self.location.clone())
-            .await
-            .unwrap_or(StateInfo {
-                index_number: 0,
-                init_version: crate::DOREA_VERSION.to_string(),
-                update_time: chrono::Local::now().timestamp(),
-            });
+        if !self.config.database.create_on_select && !self.group_exists(name) {
+            return Err(anyhow!("group `{}` not found", name));
+        }
 
-        self.check_eli_db(state.index_number as u64).await?;
+        Ok(match self.loading.entry(name.to_string()) {
+            dashmap::mapref::entry::Entry::Occupied(e) => LoadTicket::Pending(e.get().clone()),
+            dashmap::mapref::entry::Entry::Vacant(e) => {
+                // 加载完成时先加入 db_list 再移除任务，这里再检查一次
+                if self.db_list.contains_key(name) {
+                    return Ok(LoadTicket::Loaded);
+                }
 
-        let db = DataBase::init(
-            name.to_string(),
-            self.location.clone().join("storage"),
-            self.config.database.clone(),
-        )
-        .await;
+                let task = Arc::new(LoadTask::new());
+                e.insert(task.clone());
+                LoadTicket::Started(task)
+            }
+        })
+    }
 
-        self.db_list
-            .insert(name.to_string(), Arc::new(RwLock::new(db)));
-        self.eli_queue
-            .lock()
-            .await
-            .insert(name.to_string(), 2);
+    /// 执行加载并记录结果，状态依次为 LOADING -> NORMAL（失败时回到未加载）
+    async fn load_group(&self, name: &str, task: &Arc<LoadTask>) {
+        {
+            let mut states = DB_STATE.lock().await;
+            if states.get(name) != Some(&DataBaseState::LOCKED) {
+                states.insert(name.to_string(), DataBaseState::LOADING);
+            }
+        }
 
-        Ok(())
-    }
+        let result = async {
+            let state = DataBase::state(name.to_string(), self.location.clone())
+                .await
+                .unwrap_or_default();
+
+            self.check_eli_db(state.memory_estimate()).await?;
+
+            let db = DataBase::init(
+                name.to_string(),
+                self.location.clone().join("storage"),
+                self.config.database.clone(),
+                task.progress.clone(),
+            )
+            .await?;
+
+            self.db_list
+                .insert(name.to_string(), Arc::new(RwLock::new(db)));
+            self.eli_queue
+                .lock()
+                .await
+                .insert(name.to_string(), 1.0);
 
-    pub async fn load_from(&self, name: &str, db: Arc<RwLock<DataBase>>) -> Result<()> {
-        let db_size = db.read().await.size() as u64;
-        self.check_eli_db(db_size).await?;
+            Ok(())
+        }
+        .await;
 
-        self.db_list.insert(name.to_string(), db);
-        self.eli_queue
-            .lock()
-            .await
-            .insert(name.to_string(), 1);
+        {
+            let mut states = DB_STATE.lock().await;
+            if states.get(name) == Some(&DataBaseState::LOADING) {
+                match result {
+                    Ok(_) => states.insert(name.to_string(), DataBaseState::NORMAL),
+                    Err(_) => states.remove(name),
+                };
+            }
+        }
 
-        Ok(())
+        if let Err(e) = &result {
+            log::error!("database load error for {}: {}", name, e);
+        }
+
+        self.loading.remove_if(name, |_, v| Arc::ptr_eq(v, task));
+        task.finish(result);
     }
 
     // 预加载所需要的数据库数据
     async fn load_database(
         config: &DoreaFileConfig,
         location: PathBuf,
-    ) -> (DashMap<String, Arc<RwLock<DataBase>>>, HashMap<String, isize>) {
+    ) -> (DashMap<String, Arc<RwLock<DataBase>>>, HashMap<String, f64>) {
         let config = config.clone();
 
         let db_list = DashMap::new();
@@ -193,23 +328,30 @@ impl DataBaseManager {
         let groups = &config.database.pre_load_group;
 
         for db in groups {
-            db_list.insert(
+            let result = DataBase::init(
                 db.to_string(),
-                Arc::new(RwLock::new(
-                    DataBase::init(
-                        db.to_string(),
-                        location.clone().join("storage"),
-                        config.database.clone(),
-                    )
-                    .await,
-                )),
-            );
-            eli_que.insert(db.to_string(), 2);
+                location.clone().join("storage"),
+                config.database.clone(),
+                Arc::default(),
+            )
+            .await;
+
+            match result {
+                Ok(v) => {
+                    db_list.insert(db.to_string(), Arc::new(RwLock::new(v)));
+                    eli_que.insert(db.to_string(), 2.0);
+                }
+                Err(e) => log::error!("database load error for {}: {}", db, e),
+            }
         }
 
         let total = TOTAL_INDEX_NUMBER.load(Ordering::Relaxed);
-        let max = MAX_INDEX_NUMBER.load(Ordering::Relaxed);
-        info!("total index loaded number: {} [MAX: {}].", total, max);
+        info!(
+            "total index loaded number: {} [memory: {} / {} bytes].",
+            total,
+            crate::memory::used(),
+            crate::memory::max()
+        );
 
         (db_list, eli_que)
     }
@@ -221,9 +363,9 @@ impl DataBaseManager {
                 None => return false,
                 Some(v) => *v,
             };
-            eli.insert(db.to_string(), old + num);
+            eli.insert(db.to_string(), old + num as f64);
 
-            log::debug!("[{}] weight update to {}.", db, old + num);
+            log::debug!("[{}] weight update to {:.2}.", db, old + num as f64);
 
             return true;
         }
@@ -231,10 +373,27 @@ impl DataBaseManager {
         false
     }
 
+    /// 按 `weight_half_life` 衰减所有库的权重，`elapsed` 为距离上次衰减的秒数
+    pub async fn decay_weights(&self, elapsed: u64) {
+        let half_life = self.config.database.weight_half_life;
+        if half_life == 0 {
+            return;
+        }
+
+        let factor = 0.5_f64.powf(elapsed as f64 / half_life as f64);
+        for weight in self.eli_queue.lock().await.values_mut() {
+            *weight *= factor;
+        }
+    }
+
     pub async fn unload_database(&self, db: String) -> crate::Result<()> {
         let db_index_size = match self.db_list.get(&db) {
             Some(v) => {
                 let db_guard = v.read().await;
+                // 合并任务会在完成时修改该库的文件，卸载后再次加载会出现两个实例
+                if db_guard.merge_progress().is_running() {
+                    return Err(anyhow!("group `{}` is merging", db));
+                }
                 db_guard.save_state_json().await?;
                 db_guard.size() as u32
             }
@@ -248,748 +407,3435 @@ impl DataBaseManager {
         Ok(())
     }
 
-    pub async fn check_eli_db(&self, need: u64) -> crate::Result<()> {
-        let total_index_number = TOTAL_INDEX_NUMBER.load(Ordering::Relaxed);
-        let max_index_number = MAX_INDEX_NUMBER.load(Ordering::Relaxed);
-
-        if (total_index_number + need as u32) >= max_index_number {
-            let group_max_index_number = (max_index_number / 4) as usize;
-
-            let mut minimum = (String::new(), u64::MAX);
+    /// 删除一个库：卸载后删除它的全部数据
+    pub async fn drop_group(&self, name: &str) -> crate::Result<()> {
+        let storage: Arc<dyn StorageBackend> = match self.db_list.get(name) {
+            Some(db) => db.value().read().await.state.storage.clone(),
+            None => match self.config.database.storage_kind(name) {
+                StorageKind::Log => Arc::new(LogStorage::new(&self.location.join("storage").join(name), false)),
+                StorageKind::Memory => Arc::new(MemoryStorage::default()),
+            },
+        };
 
-            let eli = self.eli_queue.lock().await;
+        self.release_group(name, "dropped").await?;
 
-            for entry in self.db_list.iter() {
-                let name = entry.key();
-                let num = match eli.get(name) {
-                    Some(v) => *v,
-                    None => continue,
-                };
+        storage.clean().await?;
 
-                let db_guard = entry.value().read().await;
-                let db_index_number = db_guard.size() as u64;
+        log::info!("@{} group has been dropped.", name);
 
-                if db_index_number < need {
-                    continue;
-                }
+        Ok(())
+    }
 
-                if crate::server::db_stat_exist(name.to_string()).await {
-                    continue;
-                }
+    /// 将库改名，目录只改名一次，新的库名要么完整出现要么不存在
+    pub async fn rename_group(&self, old: &str, new: &str) -> crate::Result<()> {
+        check_group_name(new)?;
+        self.check_log_storage(old)?;
+        self.check_log_storage(new)?;
 
-                if *DB_STATE
-                    .lock()
-                    .await
-                    .get(name)
-                    .unwrap_or(&DataBaseState::NORMAL)
-                    == DataBaseState::LOCKED
-                {
-                    continue;
-                }
+        if self.group_exists(new) || self.loading.contains_key(new) {
+            return Err(anyhow!("group `{}` already exists", new));
+        }
 
-                let final_weight = num as u64 * (group_max_index_number as u64 / db_index_number);
+        self.release_group(old, "renamed").await?;
 
-                if minimum.1 > final_weight {
-                    minimum = (name.to_string(), final_weight);
-                }
-            }
+        let storage = self.location.join("storage");
+        tokio::fs::rename(storage.join(old), storage.join(new)).await?;
 
-            drop(eli);
+        log::info!("@{} group has been renamed to @{}.", old, new);
 
-            if minimum.1 != u64::MAX {
-                log::info!(
-                    "weight judge: @{}[:{}] will be eliminate.",
-                    minimum.0,
-                    minimum.1
-                );
-                self.unload_database(minimum.0.to_string()).await?;
-            } else {
-                log::error!("no database can be eliminate.");
-                return Err(anyhow!("no database can be eliminate."));
-            }
-        }
         Ok(())
     }
-}
-
-#[allow(dead_code)]
-#[derive(Deserialize, Clone, Debug)]
-pub struct StateInfo {
-    pub(crate) index_number: usize,
-    pub(crate) init_version: String,
-    pub(crate) update_time: i64,
-}
-
-#[allow(dead_code)]
-impl DataBase {
-    pub async fn state(name: String, location: PathBuf) -> crate::Result<StateInfo> {
-        let location = location.join("storage").join(&name);
 
-        let v = fs::read_to_string(location.join("state.json"))?;
-        let s = serde_json::from_str::<StateInfo>(&v)?;
+    /// 复制一个库：`link` 为 true 时归档文件与源库共享（fork），否则完整复制（copy）
+    ///
+    /// 写入任务只在硬链接归档文件、复制活跃文件时暂停，完整复制归档文件在这之后进行。
+    pub async fn clone_group(&self, source: &str, target: &str, link: bool) -> crate::Result<()> {
+        check_group_name(target)?;
 
-        Ok(s)
-    }
+        // system 库保存了账号与密码，复制后就可以被普通账号读取
+        if source == "system" {
+            return Err(anyhow!("group `system` cannot be copied"));
+        }
 
-    pub async fn init(name: String, location: PathBuf, _config: DataBaseConfig) -> Self {
-        let location = location.join(&name);
+        self.check_log_storage(source)?;
+        self.check_log_storage(target)?;
 
-        let data_file = DataFile::new(&location, name.clone());
+        if !self.group_exists(source) {
+            return Err(anyhow!("group `{}` not found", source));
+        }
 
-        let mut index_list = HashMap::new();
+        if self.group_exists(target) || self.loading.contains_key(target) {
+            return Err(anyhow!("group `{}` already exists", target));
+        }
 
-        let _ = data_file.load_index(&mut index_list).await;
+        self.select_to(source).await?;
 
-        let obj = Self {
-            name: name.clone(),
-            index: index_list,
-            timestamp: chrono::Local::now().timestamp(),
-            file: data_file,
-            location,
+        let db = match self.db_list.get(source) {
+            Some(v) => v.value().clone(),
+            None => return Err(anyhow!("group `{}` not loaded", source)),
         };
 
-        let _ = obj.save_state_json().await;
+        let storage = self.location.join("storage");
+        let temp = storage.join(format!("~fork-{}", target));
+        if temp.exists() {
+            tokio::fs::remove_dir_all(&temp).await?;
+        }
 
-        obj
-    }
+        db.read().await.fork(temp.clone()).await?;
 
-    pub async fn save_state_json(&self) -> crate::Result<()> {
-        let path = self.location.clone();
+        if !link {
+            let root = temp.clone();
+            if let Err(e) = tokio::task::spawn_blocking(move || unshare_archives(&root)).await? {
+                let _ = tokio::fs::remove_dir_all(&temp).await;
+                return Err(e);
+            }
+        }
 
-        fs::write(
-            path.join("state.json"),
-            serde_json::json!({
-                "index_number": self.size(),
-                "init_version": crate::DOREA_VERSION,
-                "update_time": chrono::Local::now().timestamp(),
-            })
-            .to_string()
-            .as_bytes(),
-        )?;
+        tokio::fs::rename(&temp, storage.join(target)).await?;
+
+        log::info!(
+            "@{} group has been {} to @{}.",
+            source,
+            if link { "forked" } else { "copied" },
+            target
+        );
 
         Ok(())
     }
 
-    pub async fn set(&mut self, key: &str, value: DataValue, expire: u64) -> Result<()> {
-        if !self.contains_key(key).await && value != DataValue::None {
-            let max_index_number = MAX_INDEX_NUMBER.load(Ordering::Relaxed);
-
-            if TOTAL_INDEX_NUMBER.load(Ordering::Relaxed) >= max_index_number {
-                return Err(anyhow!("exceeded system max index number"));
-            }
+    /// 读取单库配置，未加载的库直接读取库目录中的文件
+    pub async fn group_config(&self, name: &str) -> crate::Result<GroupConfig> {
+        if let Some(db) = self.db_list.get(name).map(|v| v.value().clone()) {
+            return Ok(db.read().await.config().clone());
+        }
 
-            if (self.index.len() as u32) >= (max_index_number / (INDEX_PROPORTION_FOR_DB as u32)) {
-                return Err(anyhow!("exceeded group max index number"));
-            }
+        if !self.group_exists(name) {
+            return Err(anyhow!("group `{}` not found", name));
         }
 
-        let mut crc_digest = CASTAGNOLI.digest();
-        crc_digest.update(value.to_string().as_bytes());
+        load_group_config(&self.location.join("storage").join(name))
+    }
 
-        let data_node = DataNode {
-            crc: crc_digest.finalize(),
-            key: key.to_string(),
-            value: value.clone(),
-            time_stamp: (chrono::Local::now().timestamp(), expire),
-        };
+    /// 修改单库配置中的一项，已加载的库立即生效
+    pub async fn set_group_config(&self, name: &str, key: &str, value: &str) -> crate::Result<GroupConfig> {
+        if !self.group_exists(name) {
+            return Err(anyhow!("group `{}` not found", name));
+        }
 
-        self.file.write(data_node, &mut self.index).await
-    }
+        // 加载中的库已经读取过配置文件，修改会在加载完成后丢失
+        if self.loading.contains_key(name) {
+            return Err(anyhow!("group `{}` is loading", name));
+        }
 
-    pub async fn get(&self, key: &str) -> Option<DataValue> {
-        let res = self.file.read(key.to_string(), &self.index).await;
-        match res {
-            Some(d) => {
-                if d.time_stamp.1 != 0
-                    && (d.time_stamp.0 as u64 + d.time_stamp.1)
-                        < chrono::Local::now().timestamp() as u64
-                {
-                    return Some(DataValue::None);
-                }
+        if let Some(db) = self.db_list.get(name).map(|v| v.value().clone()) {
+            let mut db = db.write().await;
+            let mut config = db.config().clone();
+            config.set(key, value)?;
+            db.set_config(config.clone())?;
+            return Ok(config);
+        }
 
-                Some(d.value)
-            }
-            None => None,
+        // 内存存储的库没有数据目录，配置只在加载后保存在内存中
+        if self.config.database.storage_kind(name) == StorageKind::Memory {
+            return Err(anyhow!("group `{}` not loaded", name));
         }
-    }
 
-    pub async fn meta_data(&self, key: &str) -> Option<DataNode> {
-        self.file.read(key.to_string(), &self.index).await
-    }
+        let root = self.location.join("storage").join(name);
+        let mut config = load_group_config(&root)?;
+        config.set(key, value)?;
+        save_group_config(&root, &config)?;
 
-    pub async fn delete(&mut self, key: &str) -> Result<()> {
-        return match self.set(key, DataValue::None, 0).await {
-            Ok(_) => {
-                TOTAL_INDEX_NUMBER.fetch_sub(1, Ordering::Relaxed);
-                self.index.remove(key);
-                Ok(())
-            }
-            Err(e) => Err(e),
-        };
+        Ok(config)
     }
 
-    pub async fn contains_key(&self, key: &str) -> bool {
-        self.index.contains_key(key)
-    }
+    /// 删除或改名之前卸载库，并等待已经提交的写入完成
+    async fn release_group(&self, name: &str, action: &str) -> crate::Result<()> {
+        check_group_name(name)?;
 
-    pub async fn clean(&mut self) -> Result<()> {
-        TOTAL_INDEX_NUMBER.fetch_sub(self.index.len() as u32, Ordering::Relaxed);
-        for entry in walkdir::WalkDir::new(&self.location)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.path().is_file() {
-                fs::remove_file(entry.path())?;
-            }
+        if name == "system" || name == self.config.database.default_group {
+            return Err(anyhow!("group `{}` cannot be {}", name, action));
         }
 
-        self.index = HashMap::new();
+        if !self.group_exists(name) {
+            return Err(anyhow!("group `{}` not found", name));
+        }
 
-        self.file.init_db()?;
+        if self.loading.contains_key(name) {
+            return Err(anyhow!("group `{}` is loading", name));
+        }
 
-        info!("@{} group has been clean.", self.name);
+        if crate::server::db_stat_exist(name.to_string()).await {
+            return Err(anyhow!("group `{}` is in use", name));
+        }
 
-        Ok(())
-    }
+        if DB_STATE.lock().await.get(name) == Some(&DataBaseState::LOCKED) {
+            return Err(anyhow!("group `{}` is locked", name));
+        }
 
-    pub async fn keys(&self) -> Vec<String> {
-        let mut temp = vec![];
-        for i in self.index.keys() {
-            temp.push(i.to_string());
+        if let Some(db) = self.db_list.get(name).map(|v| v.value().clone()) {
+            self.unload_database(name.to_string()).await?;
+            // 持有读锁的命令都已完成，不会再有写入落到即将移动的文件中
+            drop(db.write().await);
         }
+        DB_STATE.lock().await.remove(name);
 
-        temp
+        Ok(())
     }
 
-    pub fn record_count(&self) -> usize {
-        self.file.record_count()
+    /// 基于数据文件的操作（复制、快照、校验等）不支持内存存储的库
+    fn check_log_storage(&self, name: &str) -> crate::Result<()> {
+        match self.config.database.storage_kind(name) {
+            StorageKind::Log => Ok(()),
+            StorageKind::Memory => Err(anyhow!("group `{}` uses memory storage", name)),
+        }
     }
 
-    pub fn size(&self) -> usize {
-        self.index.len()
-    }
+    /// 检查某个库的数据文件完整性（未加载的库直接读取磁盘文件）
+    pub async fn verify_group(&self, name: &str) -> crate::Result<VerifyReport> {
+        self.check_log_storage(name)?;
 
-    pub async fn merge(&mut self) -> crate::Result<()> {
-        self.file.merge_struct(&mut self.index).await
-    }
-}
+        let loaded = self.db_list.get(name).map(|v| v.value().clone());
 
-impl DataNode {
-    pub(crate) fn timestamp(&self) -> (i64, u64) {
-        self.time_stamp
-    }
-    pub(crate) fn weight(self) -> f64 {
-        self.value.weight()
-    }
-}
+        if let Some(db) = loaded {
+            // 持有读锁，检查期间不会有合并替换数据文件
+            let db = db.read().await;
+            return db.verify().await;
+        }
 
-/// 缓存的文件写入器，避免每次写入都打开文件
-struct DataFileWriter {
-    file: tokio::fs::File,
-    /// 当前写入位置，避免每次调用 metadata()
-    write_position: u64,
-}
+        let root = self.location.join("storage").join(name);
+        if !root.is_dir() {
+            return Err(anyhow!("group `{}` not found", name));
+        }
 
-impl std::fmt::Debug for DataFileWriter {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("DataFileWriter")
-            .field("write_position", &self.write_position)
-            .finish_non_exhaustive()
+        let name = name.to_string();
+        tokio::task::spawn_blocking(move || {
+            let file_id = LogStorage::new(&root, false).init_db()?;
+            verify_files(&name, &root, file_id, RecordReader::open(&root.join("active.db")))
+        })
+        .await?
     }
-}
 
-#[derive(Debug)]
-struct DataFile {
-    root: PathBuf,
-    name: String,
-    /// 缓存的文件句柄和写入位置
-    writer: Option<DataFileWriter>,
-}
+    /// 在后台合并某个已加载的库，已有合并在运行时直接返回当前进度
+    pub async fn merge_group(&self, name: &str) -> crate::Result<Arc<MergeProgress>> {
+        let db = match self.db_list.get(name) {
+            Some(v) => v.value().clone(),
+            None => return Err(anyhow!("group `{}` not loaded", name)),
+        };
 
-impl Clone for DataFile {
-    fn clone(&self) -> Self {
-        // Clone 时不复制文件句柄，让新实例自己打开
-        Self {
-            root: self.root.clone(),
-            name: self.name.clone(),
-            writer: None,
+        let progress = db.read().await.merge_progress();
+
+        if !progress.try_start() {
+            return Ok(progress);
         }
-    }
-}
 
-impl DataFile {
-    pub fn new(root: &Path, name: String) -> Self {
-        let mut db = Self {
-            root: root.to_path_buf(),
-            name,
-            writer: None,
-        };
+        progress
+            .started_at
+            .store(chrono::Local::now().timestamp(), Ordering::Relaxed);
+        progress.begin(0);
 
-        db.init_db().unwrap();
+        let name = name.to_string();
+        let task_progress = progress.clone();
+
+        tokio::spawn(async move {
+            let result = DataBase::merge(&db).await;
+            if let Err(e) = &result {
+                log::error!("merge operation error for {}: {}", name, e);
+            }
+            task_progress.finish(&result);
+        });
 
-        db
+        Ok(progress)
     }
 
-    pub async fn load_index(&self, index: &mut HashMap<String, IndexInfo>) -> crate::Result<()> {
-        if !self.root.is_dir() {
-            return Err(anyhow!("root dir not found"));
+    /// 生成某个库的快照，写入只在冻结文件列表时短暂暂停
+    pub async fn snapshot_group(&self, name: &str, target: &Path) -> crate::Result<Manifest> {
+        // system 库保存了账号与密码，不能导出到服务端的任意路径
+        if name == "system" {
+            return Err(anyhow!("group `system` cannot be snapshotted"));
         }
 
-        let mut count = 0;
+        self.check_log_storage(name)?;
 
-        for entry in walkdir::WalkDir::new(&self.root)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.path().is_file() {
-                let file_name = entry.path().file_name().unwrap().to_str().unwrap();
-
-                let info: nom::IResult<&str, &str> = nom::sequence::delimited(
-                    nom::bytes::complete::tag("archive-"),
-                    nom::character::complete::digit1,
-                    nom::bytes::complete::tag(".db"),
-                )(file_name);
-
-                if info.is_ok() || file_name == "active.db" {
-                    let file_id = if file_name == "active.db" {
-                        self.get_file_id()
-                    } else {
-                        info.as_ref().unwrap().1.parse::<u32>().unwrap()
-                    };
-
-                    let mut file = match OpenOptions::new().read(true).open(entry.path()) {
-                        Ok(v) => v,
-                        Err(_) => {
-                            continue;
-                        }
-                    };
-
-                    let file_size = file.metadata().unwrap().len();
-                    let file_size = file_size - 34;
-                    let mut readed_size = 0;
-
-                    file.seek(SeekFrom::Start(34))?;
-
-                    let mut legacy: Vec<u8> = vec![];
-                    let mut position: (u64, u64) = (34, 34);
-                    let mut buf = [0_u8; 1024];
-
-                    while readed_size < file_size {
-                        let v = file.read(&mut buf)?;
-                        let mut bs = bytes::BytesMut::with_capacity(v);
-
-                        bs.put(&buf[0..v]);
-
-                        let mut slice_symbol: bool = false;
-
-                        for rec in 0..bs.len() {
-                            if bs[rec] == b'\r' {
-                                if rec == (bs.len() - 1) {
-                                    let mut read_one = [0_u8; 1];
-                                    match file.read(&mut read_one) {
-                                        Ok(_amount) => {
-                                            readed_size += 1;
-
-                                            if read_one[0] != b'\n' {
-                                                legacy.push(bs[rec]);
-                                                position.1 += 1;
-
-                                                continue;
-                                            }
-                                        }
-                                        Err(e) => {
-                                            panic!("{}", e.to_string());
-                                        }
-                                    };
-                                } else if bs[rec + 1] != b'\n' {
-                                    legacy.push(bs[rec]);
-                                    position.1 += 1;
-                                    continue;
-                                }
-
-                                let v = match serde_json::from_slice::<DataNode>(&legacy[..]) {
-                                    Ok(v) => v,
-                                    Err(_) => break,
-                                };
-
-                                let info = IndexInfo {
-                                    file_id,
-                                    start_position: position.0,
-                                    end_position: position.1,
-                                    time_stamp: v.time_stamp,
-                                };
-
-                                if v.value != DataValue::None {
-                                    if !index.contains_key(&v.key) {
-                                        count += 1;
-                                    }
-                                    index.insert(v.key.clone(), info);
-                                } else if index.contains_key(&v.key) {
-                                    index.remove(&v.key);
-                                    count -= 1;
-                                }
-
-                                slice_symbol = true;
-                                position = (position.1 + 2, position.1 + 2);
-
-                                legacy.clear();
-                            } else if slice_symbol && bs[rec] == b'\n' {
-                                slice_symbol = false;
-                            } else {
-                                legacy.push(bs[rec]);
-                                position.1 += 1;
-                            }
-                        }
-
-                        readed_size += v as u64;
-                    }
-                }
-            }
+        if !self.location.join("storage").join(name).is_dir() {
+            return Err(anyhow!("group `{}` not found", name));
         }
 
-        info!(
-            "index information loaded from {:?} [{}].",
-            self.root.file_name().unwrap(),
-            count,
-        );
-        TOTAL_INDEX_NUMBER.fetch_add(count, Ordering::Relaxed);
+        if target.exists() && fs::read_dir(target)?.next().is_some() {
+            return Err(anyhow!("snapshot target {:?} is not empty", target));
+        }
 
-        Ok(())
-    }
+        self.select_to(name).await?;
 
-    fn init_db(&mut self) -> crate::Result<()> {
-        if self.check_db().is_err() {
-            if !self.root.is_dir() {
-                fs::create_dir_all(&self.root)?;
-            }
+        let db = match self.db_list.get(name) {
+            Some(v) => v.value().clone(),
+            None => return Err(anyhow!("group `{}` not loaded", name)),
+        };
 
-            let save_file = self.root.join("active.db");
+        let (staging, file_id) = db.write().await.freeze().await?;
 
-            if !save_file.is_file() {
-                self.active()?;
-            }
+        // 复制在锁外进行
+        let result = {
+            let staging = staging.clone();
+            let target = target.to_path_buf();
+            let name = name.to_string();
+            tokio::task::spawn_blocking(move || snapshot::write(&staging, &target, &name, file_id))
+                .await?
+        };
 
-            let record_in = self.root.join("record.in");
+        let _ = tokio::fs::remove_dir_all(&staging).await;
 
-            if !record_in.is_file() {
-                fs::write(record_in, b"1")?;
-            }
+        let manifest = result?;
 
-            let state_json = self.root.join("state.json");
-            if !state_json.is_file() {
-                fs::write(
-                    state_json,
-                    json!({
-                        "index_number": 0,
-                        "init_version": crate::DOREA_VERSION,
-                        "update_time": chrono::Local::now().timestamp(),
-                    })
-                    .to_string()
-                    .as_bytes(),
-                )?;
-            }
-        }
+        log::info!(
+            "snapshot of @{} written to {:?} [{} files].",
+            name,
+            target,
+            manifest.files.len()
+        );
 
-        Ok(())
+        Ok(manifest)
     }
 
-    fn rename_dfile(&mut self, new_name: &str) -> crate::Result<()> {
-        let new_root = self.root.parent().unwrap().join(new_name);
+    /// 将快照恢复为一个新的库
+    pub async fn restore_group(&self, source: &Path, name: &str) -> crate::Result<Manifest> {
+        check_group_name(name)?;
 
-        if new_root.is_dir() {
-            fs::remove_dir_all(&new_root)?;
+        if name == "system" {
+            return Err(anyhow!("group `system` cannot be restored"));
         }
 
-        fs::rename(&self.root, &new_root)?;
+        self.check_log_storage(name)?;
 
-        self.name = new_name.into();
-        self.root = new_root;
+        let storage = self.location.join("storage");
+        let root = storage.join(name);
 
-        Ok(())
-    }
+        if root.exists() || self.db_list.contains_key(name) {
+            return Err(anyhow!("group `{}` already exists", name));
+        }
 
-    fn check_db(&self) -> crate::Result<()> {
-        let mut result: crate::Result<()> = Ok(());
+        let temp = storage.join(format!("~restore-{}", name));
 
-        if !self.root.is_dir() {
-            result = Err(anyhow!("root dir not found"));
-        }
+        let result = {
+            let source = source.to_path_buf();
+            let temp = temp.clone();
+            tokio::task::spawn_blocking(move || -> crate::Result<Manifest> {
+                let manifest = snapshot::restore(&source, &temp)?;
 
-        let save_file = self.root.join("active.db");
-        let index_dir = self.root.join("record.in");
+                // 同样不能把 system 库的快照恢复为一个普通账号可以读取的库
+                if manifest.group == "system" {
+                    return Err(anyhow!("snapshot of group `system` cannot be restored"));
+                }
 
-        if !save_file.is_file() || !index_dir.is_file() {
-            result = Err(anyhow!("file not found"));
-        }
+                fs::write(temp.join("record.in"), manifest.file_id.to_string())?;
+                fs::write(temp.join("active.db"), record::file_header())?;
 
-        let mut file = fs::File::open(save_file)?;
+                Ok(manifest)
+            })
+            .await?
+        };
+
+        let manifest = match result {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&temp).await;
+                return Err(e);
+            }
+        };
 
-        let mut buf = [0; 33];
+        tokio::fs::rename(&temp, &root).await?;
 
-        file.read_exact(&mut buf)?;
+        self.select_to(name).await?;
 
-        if buf.get(buf.len() - 2).unwrap() == &b'\r' && buf.get(buf.len() - 2).unwrap() == &b'\n' {
-            result = Err(anyhow!("version nonsupport"));
-        }
+        log::info!(
+            "snapshot {:?} of @{} restored as @{}.",
+            source,
+            manifest.group,
+            name
+        );
 
-        let check_code = String::from_utf8_lossy(&buf[0..buf.len() - 1]).to_string();
+        Ok(manifest)
+    }
 
-        if !crate::COMPATIBLE_VERSION.contains(&check_code) {
-            panic!("database storage structure unsupported.");
+    /// 重放某个库截至 `timestamp`（包含该秒）的写入记录，生成一个新的库
+    pub async fn restore_at(
+        &self,
+        name: &str,
+        timestamp: i64,
+        new_name: &str,
+    ) -> crate::Result<serde_json::Value> {
+        // system 库中保存了账号信息，不能恢复为一个普通账号可以读取的库
+        if name == "system" {
+            return Err(anyhow!("group `system` cannot be restored"));
         }
 
-        result
-    }
+        check_group_name(new_name)?;
+        self.check_log_storage(name)?;
+        self.check_log_storage(new_name)?;
+
+        let storage = self.location.join("storage");
 
-    pub async fn write(
-        &mut self,
-        data: DataNode,
-        index: &mut HashMap<String, IndexInfo>,
-    ) -> Result<()> {
-        // 检查并处理 archive（如果需要）
-        if self.check_and_archive().await? {
-            // archive 后需要重新打开文件
-            self.writer = None;
+        if !storage.join(name).is_dir() {
+            return Err(anyhow!("group `{}` not found", name));
         }
 
-        let file_path = self.root.join("active.db");
+        let root = storage.join(new_name);
+        if root.exists() || self.db_list.contains_key(new_name) {
+            return Err(anyhow!("group `{}` already exists", new_name));
+        }
 
-        // 准备数据
-        let mut v = serde_json::to_vec(&data).expect("serialize failed");
-        v.push(13);
-        v.push(10);
+        self.select_to(name).await?;
 
-        // 获取或创建 writer
-        let writer = if let Some(ref mut w) = self.writer {
-            w
-        } else {
-            // 首次写入：打开文件并获取当前位置
-            let f = tokio::fs::OpenOptions::new()
-                .append(true)
-                .open(&file_path)
-                .await?;
-            let pos = f.metadata().await?.len();
-            self.writer = Some(DataFileWriter {
-                file: f,
-                write_position: pos,
-            });
-            self.writer.as_mut().unwrap()
+        let db = match self.db_list.get(name) {
+            Some(v) => v.value().clone(),
+            None => return Err(anyhow!("group `{}` not loaded", name)),
         };
 
-        let start_position = writer.write_position;
-
-        // 写入数据
-        writer.file.write_all(&v[..]).await?;
-        writer.write_position += v.len() as u64;
+        // 冻结当前的归档文件，重放在锁外进行
+        let (staging, _) = db.write().await.freeze().await?;
 
-        let end_position: u64 = start_position + v.len() as u64 - 2;
+        let temp = storage.join(format!("~restore-{}", new_name));
 
-        let index_info = IndexInfo {
-            file_id: self.get_file_id(),
-            start_position,
-            end_position,
-            time_stamp: data.time_stamp,
+        let result = {
+            let staging = staging.clone();
+            let temp = temp.clone();
+            tokio::task::spawn_blocking(move || replay_until(&staging, timestamp, &temp)).await?
         };
 
-        if !index.contains_key(&data.key) {
-            TOTAL_INDEX_NUMBER.fetch_add(1, Ordering::Relaxed);
-        }
-
-        index.insert(data.key.clone(), index_info);
+        let _ = tokio::fs::remove_dir_all(&staging).await;
 
-        Ok(())
+        let keys = match result {
+            Ok(v) => v,
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&temp).await;
+                return Err(e);
+            }
+        };
+
+        tokio::fs::rename(&temp, &root).await?;
+
+        self.select_to(new_name).await?;
+
+        log::info!(
+            "@{} restored at {} as @{} [{} keys].",
+            name,
+            timestamp,
+            new_name,
+            keys
+        );
+
+        Ok(json!({
+            "group": new_name,
+            "source": name,
+            "timestamp": timestamp,
+            "keys": keys,
+        }))
     }
 
-    pub async fn read(&self, key: String, index: &HashMap<String, IndexInfo>) -> Option<DataNode> {
-        match index.get(&key) {
-            Some(v) => self.read_with_index_info(v).await,
-            None => None,
+    pub async fn merge_status(&self, name: &str) -> crate::Result<Arc<MergeProgress>> {
+        match self.db_list.get(name) {
+            Some(v) => Ok(v.value().read().await.merge_progress()),
+            None => Err(anyhow!("group `{}` not loaded", name)),
         }
     }
 
-    #[allow(clippy::slow_vector_initialization)]
-    pub async fn read_with_index_info(&self, index_info: &IndexInfo) -> Option<DataNode> {
-        let data_file = if index_info.file_id == self.get_file_id() {
-            self.root.join("active.db")
-        } else {
-            self.root.join(format!("archive-{}.db", index_info.file_id))
-        };
+    /// 内存不足以再加载 `need` 字节时，按权重依次卸载其他库，直到可以加载为止
+    pub async fn check_eli_db(&self, need: u64) -> crate::Result<()> {
+        let max_memory = crate::memory::max();
 
-        if !data_file.is_file() {
-            return None;
+        // 卸载的库在写入任务退出后才会归还内存，这里自行累计已经释放的部分
+        let mut freed = 0_u64;
+
+        while crate::memory::used().saturating_sub(freed) + need >= max_memory {
+            let victim = self
+                .unload_candidates()
+                .await
+                .into_iter()
+                .filter(|v| v.blocked.is_none())
+                .min_by(|a, b| a.score.total_cmp(&b.score));
+
+            let victim = match victim {
+                Some(v) => v,
+                None => {
+                    log::error!("no database can be eliminate.");
+                    return Err(anyhow!("no database can be eliminate."));
+                }
+            };
+
+            log::info!(
+                "weight judge: @{}[:{:.4}] will be eliminate, {} bytes released.",
+                victim.name,
+                victim.score,
+                victim.memory
+            );
+            self.unload_database(victim.name).await?;
+            freed += victim.memory;
         }
 
-        let mut file = tokio::fs::File::open(&data_file).await.ok()?;
+        Ok(())
+    }
 
-        file.seek(SeekFrom::Start(index_info.start_position))
-            .await
-            .ok()?;
+    /// 评估每个已加载的库能否被自动卸载，以及卸载的先后（score 越小越先卸载）
+    pub async fn unload_candidates(&self) -> Vec<UnloadCandidate> {
+        let group_max_memory = (crate::memory::max() / MEMORY_PROPORTION_FOR_DB) as f64;
+        let pinned = &self.config.database.pinned_groups;
+
+        let eli = self.eli_queue.lock().await.clone();
+        let db_list: Vec<(String, Arc<RwLock<DataBase>>)> = self
+            .db_list
+            .iter()
+            .map(|v| (v.key().clone(), v.value().clone()))
+            .collect();
+
+        let mut result = vec![];
+
+        for (name, db) in db_list {
+            let weight = eli.get(&name).copied().unwrap_or(0.0);
+
+            let (memory, merging, kind) = {
+                let db_guard = db.read().await;
+                (
+                    db_guard.memory(),
+                    db_guard.merge_progress().is_running(),
+                    db_guard.storage_kind(),
+                )
+            };
 
-        let mut buf: Vec<u8> =
-            Vec::with_capacity((index_info.end_position - index_info.start_position) as usize);
+            let locked = *DB_STATE
+                .lock()
+                .await
+                .get(&name)
+                .unwrap_or(&DataBaseState::NORMAL)
+                == DataBaseState::LOCKED;
+
+            let blocked = if pinned.contains(&name) {
+                Some("pinned")
+            } else if locked {
+                Some("locked")
+            } else if merging {
+                Some("merging")
+            } else if kind == StorageKind::Memory {
+                // 卸载会丢弃内存存储的全部数据
+                Some("memory storage")
+            } else if crate::server::db_stat_exist(name.clone()).await {
+                Some("in use")
+            } else if memory == 0 {
+                Some("empty")
+            } else {
+                None
+            };
 
-        buf.resize(
-            (index_info.end_position - index_info.start_position) as usize,
-            0,
-        );
+            // 访问越少、占用内存越多的库越先被卸载
+            let score = weight.max(0.0) * group_max_memory / memory.max(1) as f64;
 
-        let len = file.read(&mut buf).await.ok()?;
+            result.push(UnloadCandidate {
+                name,
+                weight,
+                memory,
+                score,
+                blocked,
+            });
+        }
 
-        let v = match serde_json::from_slice::<DataNode>(buf[0..len].as_bytes()) {
-            Ok(v) => v,
-            Err(_) => {
-                return None;
+        result
+    }
+
+    /// 自动卸载的评估报告：每个库的权重、占用与不能卸载的原因，以及下一个会被卸载的库
+    pub async fn eviction_report(&self) -> serde_json::Value {
+        let candidates = self.unload_candidates().await;
+
+        let next = candidates
+            .iter()
+            .filter(|v| v.blocked.is_none())
+            .min_by(|a, b| a.score.total_cmp(&b.score))
+            .map(|v| v.name.clone());
+
+        let groups: serde_json::Map<String, serde_json::Value> = candidates
+            .into_iter()
+            .map(|v| {
+                let reason = match v.blocked {
+                    Some(reason) => reason,
+                    None if Some(&v.name) == next.as_ref() => "next to unload",
+                    None => "unloadable",
+                };
+
+                (
+                    v.name,
+                    json!({
+                        "weight": v.weight,
+                        "memory": v.memory,
+                        "score": v.score,
+                        "unloadable": v.blocked.is_none(),
+                        "reason": reason,
+                    }),
+                )
+            })
+            .collect();
+
+        json!({
+            "used": crate::memory::used(),
+            "max": crate::memory::max(),
+            "half_life": self.config.database.weight_half_life,
+            "next": next,
+            "groups": groups,
+        })
+    }
+}
+
+/// 库名会作为数据目录名使用，不能包含路径分隔符，也不能与临时目录冲突
+fn check_group_name(name: &str) -> crate::Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with(['.', '~']) {
+        return Err(anyhow!("invalid group name `{}`", name));
+    }
+
+    Ok(())
+}
+
+enum LoadTicket {
+    Loaded,
+    /// 已经有其他请求在加载
+    Pending(Arc<LoadTask>),
+    /// 由本次请求负责加载
+    Started(Arc<LoadTask>),
+}
+
+/// 库的加载进度（数据文件的字节数）
+#[derive(Debug, Default)]
+pub struct LoadProgress {
+    pub(crate) total_bytes: AtomicU64,
+    pub(crate) scanned_bytes: AtomicU64,
+}
+
+impl LoadProgress {
+    pub(crate) fn begin(&self, total_bytes: u64) {
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.scanned_bytes.store(0, Ordering::Relaxed);
+    }
+}
+
+/// 正在进行的一次库加载
+#[derive(Debug)]
+pub struct LoadTask {
+    progress: Arc<LoadProgress>,
+    started_at: i64,
+    /// 加载结果，完成之前为 None
+    done: watch::Sender<Option<std::result::Result<(), String>>>,
+}
+
+impl LoadTask {
+    fn new() -> Self {
+        Self {
+            progress: Arc::default(),
+            started_at: chrono::Local::now().timestamp(),
+            done: watch::channel(None).0,
+        }
+    }
+
+    fn finish(&self, result: Result<()>) {
+        self.done.send_replace(Some(result.map_err(|e| e.to_string())));
+    }
+
+    async fn wait(&self) -> Result<()> {
+        let mut done = self.done.subscribe();
+
+        loop {
+            if let Some(result) = done.borrow_and_update().clone() {
+                return result.map_err(|e| anyhow!(e));
             }
-        };
 
-        Some(v)
+            if done.changed().await.is_err() {
+                return Err(anyhow!("group loading cancelled"));
+            }
+        }
+    }
+
+    pub fn report(&self) -> serde_json::Value {
+        json!({
+            "scanned_bytes": self.progress.scanned_bytes.load(Ordering::Relaxed),
+            "total_bytes": self.progress.total_bytes.load(Ordering::Relaxed),
+            "started_at": self.started_at,
+        })
     }
+}
+
+/// 自动卸载时对某个库的评估
+#[derive(Debug, Clone)]
+pub struct UnloadCandidate {
+    pub(crate) name: String,
+    pub(crate) weight: f64,
+    pub(crate) memory: u64,
+    /// 卸载的先后，越小越先卸载
+    pub(crate) score: f64,
+    /// 不能被卸载的原因
+    pub(crate) blocked: Option<&'static str>,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize, Clone, Debug)]
+pub struct StateInfo {
+    pub(crate) index_number: usize,
+    pub(crate) init_version: String,
+    pub(crate) update_time: i64,
+    /// 上次保存时索引占用的内存（旧版本的 state.json 中没有该字段）
+    #[serde(default)]
+    pub(crate) memory: u64,
+}
 
-    /// 检查文件是否需要 archive，如果需要则执行
-    /// 返回 true 表示执行了 archive
-    async fn check_and_archive(&mut self) -> crate::Result<bool> {
-        let file = self.root.join("active.db");
+impl Default for StateInfo {
+    fn default() -> Self {
+        Self {
+            index_number: 0,
+            init_version: crate::DOREA_VERSION.to_string(),
+            update_time: chrono::Local::now().timestamp(),
+            memory: 0,
+        }
+    }
+}
 
-        if !file.is_file() {
-            self.active()?;
-            return Ok(false);
+impl StateInfo {
+    /// 加载该库预计需要的内存
+    pub(crate) fn memory_estimate(&self) -> u64 {
+        match self.memory {
+            0 => self.index_number as u64 * (INDEX_ENTRY_OVERHEAD + 16),
+            v => v,
         }
+    }
+}
 
-        // 使用缓存的 write_position 或获取文件大小
-        let size = if let Some(ref writer) = self.writer {
-            writer.write_position
-        } else {
-            tokio::fs::metadata(&file).await?.len()
+#[allow(dead_code)]
+impl DataBase {
+    pub async fn state(name: String, location: PathBuf) -> crate::Result<StateInfo> {
+        let location = location.join("storage").join(&name);
+
+        let v = tokio::fs::read_to_string(location.join("state.json")).await?;
+        let s = serde_json::from_str::<StateInfo>(&v)?;
+
+        Ok(s)
+    }
+
+    pub async fn init(
+        name: String,
+        location: PathBuf,
+        config: DataBaseConfig,
+        progress: Arc<LoadProgress>,
+    ) -> crate::Result<Self> {
+        let location = location.join(&name);
+
+        let history_depth = config.history_depth.get(&name).copied().unwrap_or(0);
+        let cache_size = config.value_cache.get(&name).copied().unwrap_or(0);
+        let eviction = config.eviction_policy.get(&name).copied().unwrap_or_default();
+
+        let kind = config.storage_kind(&name);
+
+        // 内存存储的库不读写磁盘，单库配置只保存在内存中
+        let group_config = match kind {
+            StorageKind::Log => match load_group_config(&location) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("group config load failed for {:?}: {}.", location, e);
+                    GroupConfig::default()
+                }
+            },
+            StorageKind::Memory => GroupConfig::default(),
+        };
+
+        let (storage, durability): (Arc<dyn StorageBackend>, Durability) = match kind {
+            StorageKind::Log => (
+                Arc::new(LogStorage::new(&location, config.mmap_archives)),
+                group_config.durability.unwrap_or(config.durability),
+            ),
+            StorageKind::Memory => (Arc::new(MemoryStorage::default()), Durability::None),
+        };
+        let codec = Codec::new(group_config.compression, group_config.compress_threshold);
+
+        // 检查、迁移数据文件以及加载索引都是阻塞的文件操作，放到阻塞线程池中执行，
+        // 加载大库时不会占用处理其他连接的工作线程
+        let data_file = {
+            let name = name.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut data_file = DataFile::new(
+                    &location,
+                    name.clone(),
+                    storage,
+                    durability,
+                    codec,
+                    history_depth,
+                    cache_size,
+                );
+
+                // 数据文件无法读取（例如密钥错误）时不加载，避免在空索引上继续写入
+                data_file
+                    .load_index(&progress)
+                    .map_err(|e| anyhow!("index load failed for {:?}: {}", location, e))?;
+
+                Ok::<_, anyhow::Error>(data_file)
+            })
+            .await
+            .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))?
+        };
+
+        // 所有修改都交给写入任务按顺序执行，读取直接访问共享索引
+        let (writer, queue) = mpsc::channel(WRITE_QUEUE_SIZE);
+        let state = data_file.state.clone();
+        tokio::spawn(data_file.run(queue));
+
+        let obj = Self {
+            name,
+            timestamp: chrono::Local::now().timestamp(),
+            location: state.root.clone(),
+            state,
+            writer,
+            eviction,
+            config: group_config,
         };
 
-        if size >= (1024 * 1024 * 64) {
-            // archive 前先关闭文件句柄
-            self.writer = None;
-            self.archive()?;
-            return Ok(true);
+        let _ = obj.save_state_json().await;
+
+        Ok(obj)
+    }
+
+    pub async fn save_state_json(&self) -> crate::Result<()> {
+        if self.storage_kind() == StorageKind::Memory {
+            return Ok(());
+        }
+
+        let content = serde_json::json!({
+            "index_number": self.size(),
+            "init_version": crate::DOREA_VERSION,
+            "update_time": chrono::Local::now().timestamp(),
+            "memory": self.state.memory.load(Ordering::Relaxed),
+        })
+        .to_string();
+
+        tokio::fs::write(self.location.join("state.json"), content).await?;
+
+        Ok(())
+    }
+
+    pub async fn set(&self, key: &str, value: DataValue, expire: u64) -> Result<()> {
+        self.check_writable()?;
+
+        let mut need = 0;
+        if !self.contains_key(key).await && value != DataValue::None {
+            need += index_entry_size(key);
+        }
+
+        // 内存存储的记录本身也占用内存
+        if self.storage_kind() == StorageKind::Memory {
+            need += crate::storage::record_size(key, &value);
+        }
+
+        if need > 0 {
+            if self.eviction != EvictionPolicy::NoEviction && self.memory_overflow(need) > 0 {
+                self.evict(need).await?;
+            }
+
+            self.check_memory(need)?;
+        }
+
+        let data_node = DataNode::new(
+            key.to_string(),
+            value,
+            (chrono::Local::now().timestamp(), expire),
+        );
+
+        self.submit(data_node, WriteKind::Set).await?.await
+    }
+
+    pub fn storage_kind(&self) -> StorageKind {
+        self.state.storage.kind()
+    }
+
+    pub fn config(&self) -> &GroupConfig {
+        &self.config
+    }
+
+    /// 修改单库配置并写入库目录（内存存储的库只修改内存中的配置），`durability` 在下次加载时生效
+    pub fn set_config(&mut self, config: GroupConfig) -> crate::Result<()> {
+        if self.storage_kind() == StorageKind::Log {
+            save_group_config(&self.location, &config)?;
         }
+        *self.state.codec.lock().unwrap() =
+            Codec::new(config.compression, config.compress_threshold);
+        self.config = config;
+        Ok(())
+    }
+
+    /// `set` 没有指定过期时间时使用的过期时间
+    pub fn default_ttl(&self) -> u64 {
+        self.config.default_ttl
+    }
 
-        Ok(false)
+    /// 只读或维护中的库拒绝写入
+    fn check_writable(&self) -> Result<()> {
+        match self.config.mode {
+            GroupMode::Normal => Ok(()),
+            GroupMode::ReadOnly => Err(anyhow!("group `{}` is read-only", self.name)),
+            GroupMode::Maintenance => Err(anyhow!("group `{}` is under maintenance", self.name)),
+        }
     }
 
-    pub async fn check_file(&self) -> crate::Result<()> {
-        let file = self.root.join("active.db");
+    /// 维护中的库拒绝读取
+    pub fn check_readable(&self) -> Result<()> {
+        match self.config.mode {
+            GroupMode::Maintenance => Err(anyhow!("group `{}` is under maintenance", self.name)),
+            _ => Ok(()),
+        }
+    }
 
-        if !file.is_file() {
-            self.active()?;
+    /// 本库的内存上限：单库配置的 `max_memory`，未配置时为全局上限的 1/4
+    fn max_memory(&self) -> u64 {
+        match self.config.max_memory {
+            0 => crate::memory::max() / MEMORY_PROPORTION_FOR_DB,
+            v => v,
         }
+    }
+
+    /// 写入 `need` 字节的新索引是否超出内存上限
+    fn check_memory(&self, need: u64) -> Result<()> {
+        let max_memory = crate::memory::max();
 
-        let size = tokio::fs::metadata(&file).await?.len();
+        // 缓存只使用索引剩余的内存，这里只计算索引的占用
+        if crate::memory::index() + need > max_memory {
+            return Err(anyhow!("exceeded system max memory"));
+        }
 
-        if size >= (1024 * 1024 * 64) {
-            self.archive()?;
+        if self.state.memory.load(Ordering::Relaxed) + need > self.max_memory() {
+            return Err(anyhow!("exceeded group max memory"));
         }
 
         Ok(())
     }
 
-    pub async fn merge_struct(
-        &mut self,
-        index: &mut HashMap<String, IndexInfo>,
-    ) -> crate::Result<()> {
-        let root_path = self.root.clone();
+    /// 写入 `need` 字节的新索引时超出内存上限的字节数
+    fn memory_overflow(&self, need: u64) -> u64 {
+        let max_memory = crate::memory::max();
+
+        let system = (crate::memory::index() + need).saturating_sub(max_memory);
+        let group = (self.state.memory.load(Ordering::Relaxed) + need).saturating_sub(self.max_memory());
+
+        system.max(group)
+    }
 
-        let record = tokio::fs::read_to_string(root_path.join("record.in")).await?;
-        let record = record.parse::<usize>()?;
+    /// 按淘汰策略删除本库中的冷数据，为 `need` 字节的新索引腾出空间
+    ///
+    /// 每次至少释放本库上限的 1/64；没有可以淘汰的 key 时由之后的检查返回错误。
+    async fn evict(&self, need: u64) -> Result<()> {
+        // 同时写入的连接只需要一个执行淘汰，其余等待后重新检查
+        let _guard = self.state.evicting.lock().await;
 
-        if record <= 3 {
+        let overflow = self.memory_overflow(need);
+        if overflow == 0 {
             return Ok(());
         }
 
-        let temp_dfile = root_path.parent().unwrap().join(format!("~{}", self.name));
-        let mut temp_dfile = DataFile::new(&temp_dfile, format!("~{}", self.name));
-        let mut temp_index = HashMap::new();
+        let batch = self.max_memory() / EVICTION_BATCH_PROPORTION;
+        let candidates = self.eviction_candidates(overflow.max(batch));
+        if candidates.is_empty() {
+            return Ok(());
+        }
 
-        for (_, index_info) in index.iter() {
-            let val = self.read_with_index_info(index_info).await;
-            temp_dfile
-                .write(val.unwrap(), &mut temp_index)
-                .await
-                .unwrap();
+        // 选出之后又被修改过的 key 由写入任务跳过，不会丢失新写入的值
+        let count = candidates.len();
+        let ops = candidates.into_iter().map(|(key, info)| (key, WriteKind::Evict(info)));
+        self.submit_deletes(ops).await?;
+
+        log::debug!("@{} evicted up to {} keys [{:?}].", self.name, count, self.eviction);
+
+        Ok(())
+    }
+
+    /// 按淘汰策略选出最冷的一组 key 及其当前的索引，它们的索引合计至少占用 `target` 字节
+    fn eviction_candidates(&self, target: u64) -> Vec<(String, IndexInfo)> {
+        if self.eviction == EvictionPolicy::VolatileTtl {
+            let mut freed = 0;
+            let mut keys = vec![];
+            for (_, key) in self.state.expiry.lock().unwrap().iter() {
+                if freed >= target {
+                    break;
+                }
+                freed += index_entry_size(key);
+                keys.push(key.clone());
+            }
+            return self.with_index(keys);
         }
 
-        *index = temp_index.clone();
+        let now = chrono::Local::now().timestamp();
 
-        temp_dfile.rename_dfile(&self.name)?;
+        // 大根堆中保留目前最冷的 key，堆顶是其中最热的，多余的部分从堆顶移除
+        let mut heap: BinaryHeap<((i64, i64), u64, String)> = BinaryHeap::new();
+        let mut total = 0;
 
-        log::info!("merge success: {}", self.name);
+        for entry in self.state.index.iter() {
+            let info = entry.value();
 
-        Ok(())
+            let score = match self.eviction {
+                EvictionPolicy::AllKeysLfu => (info.access.frequency(now) as i64, info.access.last()),
+                EvictionPolicy::VolatileLru if expire_deadline(info.time_stamp).is_none() => continue,
+                _ => (info.access.last(), 0),
+            };
+
+            if total >= target && heap.peek().map(|v| score >= v.0).unwrap_or(false) {
+                continue;
+            }
+
+            let size = index_entry_size(entry.key());
+            heap.push((score, size, entry.key().clone()));
+            total += size;
+
+            while let Some(top) = heap.peek() {
+                if total - top.1 < target {
+                    break;
+                }
+                total -= top.1;
+                heap.pop();
+            }
+        }
+
+        self.with_index(heap.into_iter().map(|v| v.2).collect())
     }
 
-    fn active(&self) -> crate::Result<()> {
-        let file = self.root.join("active.db");
+    /// 附上 key 当前的索引，已经不存在的 key 被忽略
+    fn with_index(&self, keys: Vec<String>) -> Vec<(String, IndexInfo)> {
+        keys.into_iter()
+            .filter_map(|key| self.index_info(&key).map(|info| (key, info)))
+            .collect()
+    }
 
-        let mut content = BytesMut::new();
+    /// 淘汰策略与已淘汰的 key 数量
+    pub fn eviction_report(&self) -> serde_json::Value {
+        json!({
+            "policy": String::from(self.eviction),
+            "evicted": self.state.evicted.load(Ordering::Relaxed),
+        })
+    }
 
-        let header_info = format!("Dorea::{}", crate::DOREA_VERSION);
+    /// 将一条记录交给写入任务，返回写入完成（索引已更新）的通知
+    async fn submit(
+        &self,
+        node: DataNode,
+        kind: WriteKind,
+    ) -> Result<impl std::future::Future<Output = Result<()>>> {
+        let (reply, done) = oneshot::channel();
 
-        let digest = md5::compute(header_info.as_bytes());
+        self.writer
+            .send(WriteOp::Put { node, kind, reply })
+            .await
+            .map_err(|_| anyhow!("group writer stopped"))?;
 
-        content.put(format!("{:x}", digest).as_bytes());
+        Ok(async move { done.await.map_err(|_| anyhow!("group writer stopped"))? })
+    }
 
-        content.put("\r\n".as_bytes());
+    /// 归档活跃文件（由写入任务执行，排在之前提交的写入之后）
+    async fn rotate(&self) -> Result<()> {
+        let (reply, done) = oneshot::channel();
 
-        fs::write(&file, content)?;
+        self.writer
+            .send(WriteOp::Rotate { reply })
+            .await
+            .map_err(|_| anyhow!("group writer stopped"))?;
 
-        Ok(())
+        done.await.map_err(|_| anyhow!("group writer stopped"))?
+    }
+
+    /// 将当前的数据文件复制到 `target`（由写入任务执行，包含之前提交的所有写入）
+    async fn fork(&self, target: PathBuf) -> Result<()> {
+        let (reply, done) = oneshot::channel();
+
+        self.writer
+            .send(WriteOp::Fork { target, reply })
+            .await
+            .map_err(|_| anyhow!("group writer stopped"))?;
+
+        done.await.map_err(|_| anyhow!("group writer stopped"))?
+    }
+
+    fn index_info(&self, key: &str) -> Option<IndexInfo> {
+        self.state.index.get(key).map(|v| v.clone())
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<DataValue>> {
+        let res = self.meta_data(key).await?;
+        match res {
+            Some(d) => {
+                if is_expired(d.time_stamp, chrono::Local::now().timestamp()) {
+                    return Ok(Some(DataValue::None));
+                }
+
+                Ok(Some(d.value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// 当前值以及历史版本（新版本在前），最多返回 `limit` 条，删除标记的值为 `None`
+    pub async fn history(&self, key: &str, limit: usize) -> Result<Vec<DataNode>> {
+        let current = self.index_info(key);
+        let versions: Vec<IndexInfo> = {
+            let history = self.state.history.lock().unwrap();
+            current
+                .into_iter()
+                .chain(history.get(key).map(|v| v.info.clone()))
+                .take(limit)
+                .collect()
+        };
+
+        let mut result = vec![];
+        for info in versions {
+            result.push(self.state.read(info).await?);
+        }
+
+        Ok(result)
+    }
+
+    /// 读取 key 在 `timestamp` 时刻的值（超出保留的历史范围时返回 `None`）
+    pub async fn get_at(&self, key: &str, timestamp: i64) -> Result<Option<DataValue>> {
+        let current = self.index_info(key);
+        let found = {
+            let history = self.state.history.lock().unwrap();
+            let found = current
+                .map(|info| (info, false))
+                .into_iter()
+                .chain(history.get(key).map(|v| (v.info.clone(), v.tombstone)))
+                .find(|(info, _)| info.time_stamp.0 <= timestamp);
+            found
+        };
+
+        let (info, tombstone) = match found {
+            Some(v) => v,
+            None => return Ok(None),
+        };
+
+        if tombstone || is_expired(info.time_stamp, timestamp) {
+            return Ok(None);
+        }
+
+        let node = self.state.read(info).await?;
+        match node.value {
+            DataValue::None => Ok(None),
+            v => Ok(Some(v)),
+        }
+    }
+
+    pub async fn meta_data(&self, key: &str) -> Result<Option<DataNode>> {
+        let info = match self.state.index.get(key) {
+            Some(v) => {
+                v.access.touch(chrono::Local::now().timestamp());
+                v.clone()
+            }
+            None => return Ok(None),
+        };
+
+        let location = (info.file_id, info.start_position);
+        if let Some(node) = self.state.cache.get(key, location) {
+            return Ok(Some(node));
+        }
+
+        let node = self.state.read(info).await?;
+        self.state.cache.insert(key, location, &node);
+
+        Ok(Some(node))
+    }
+
+    /// 记录在数据文件中占用的字节数（含记录头，压缩后的大小），内存存储的库返回 None
+    pub fn stored_size(&self, key: &str) -> Option<u64> {
+        if self.storage_kind() == StorageKind::Memory {
+            return None;
+        }
+
+        self.state
+            .index
+            .get(key)
+            .map(|v| v.end_position - v.start_position)
+    }
+
+    /// 数据缓存的容量、占用与命中统计
+    pub fn cache_report(&self) -> serde_json::Value {
+        self.state.cache.report(&self.name)
     }
 
-    fn archive(&self) -> crate::Result<()> {
-        let file = self.root.join("active.db");
+    pub async fn delete(&self, key: &str) -> Result<()> {
+        self.check_writable()?;
+
+        let node = DataNode::new(
+            key.to_string(),
+            DataValue::None,
+            (chrono::Local::now().timestamp(), 0),
+        );
 
-        let count = self.get_file_id();
+        self.submit(node, WriteKind::Delete).await?.await
+    }
 
-        rename(&file, self.root.join(format!("archive-{}.db", count)))?;
+    /// 删除多个 key：先全部提交再等待，写入任务可以将它们合并为一次追加
+    async fn delete_many(&self, keys: Vec<String>) -> Result<()> {
+        self.submit_deletes(keys.into_iter().map(|key| (key, WriteKind::Delete))).await
+    }
 
-        let mut f = OpenOptions::new()
-            .write(true)
-            .open(self.root.join("record.in"))?;
+    /// 提交多条删除（或淘汰）记录并等待全部完成
+    async fn submit_deletes(&self, ops: impl Iterator<Item = (String, WriteKind)>) -> Result<()> {
+        let now = chrono::Local::now().timestamp();
 
-        f.write_all((self.get_file_id() + 1).to_string().as_bytes())?;
+        let mut pending = vec![];
+        for (key, kind) in ops {
+            pending.push(self.submit(DataNode::new(key, DataValue::None, (now, 0)), kind).await?);
+        }
 
-        self.active()?;
+        for done in pending {
+            done.await?;
+        }
 
         Ok(())
     }
 
-    fn get_file_id(&self) -> u32 {
-        let fp = self.root.join("record.in");
+    pub async fn contains_key(&self, key: &str) -> bool {
+        self.state.index.contains_key(key)
+    }
+
+    /// 清空库：为每个 key 写入删除标记而不是直接删除数据文件
+    ///
+    /// 写入历史因此保留在数据文件中，可以通过 `db restore-at` 恢复到清空之前，
+    /// 占用的空间由之后的合并回收。
+    pub async fn clean(&self) -> Result<()> {
+        self.check_writable()?;
 
-        let mut fp = OpenOptions::new().read(true).open(fp).unwrap();
+        self.delete_many(self.keys().await).await?;
 
-        let mut num = String::new();
+        info!("@{} group has been clean.", self.name);
 
-        fp.read_to_string(&mut num).unwrap();
+        Ok(())
+    }
 
-        num.parse::<u32>().unwrap_or(1)
+    pub async fn keys(&self) -> Vec<String> {
+        self.state.index.iter().map(|v| v.key().clone()).collect()
     }
 
     pub fn record_count(&self) -> usize {
-        let fp = self.root.join("record.in");
-        fs::read_to_string(fp)
-            .ok()
-            .and_then(|s| s.parse::<usize>().ok())
-            .unwrap_or(1)
+        self.state.file_id() as usize
     }
-}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct IndexInfo {
-    file_id: u32,
-    start_position: u64,
-    end_position: u64,
-    time_stamp: (i64, u64),
-}
+    pub async fn verify(&self) -> crate::Result<VerifyReport> {
+        self.state.storage.verify(&self.state, &self.name).await
+    }
 
-pub async fn total_index_number() -> (u32, u32) {
-    (
-        TOTAL_INDEX_NUMBER.load(Ordering::Relaxed),
-        MAX_INDEX_NUMBER.load(Ordering::Relaxed),
-    )
+    pub fn size(&self) -> usize {
+        self.state.index.len()
+    }
+
+    /// 索引（包括历史版本）与缓存占用的内存
+    pub fn memory(&self) -> u64 {
+        self.state.memory.load(Ordering::Relaxed) + self.state.cache.size()
+    }
+
+    pub fn memory_report(&self) -> serde_json::Value {
+        let index = self.state.memory.load(Ordering::Relaxed);
+        let cache = self.state.cache.size();
+
+        json!({
+            "index": index,
+            "cache": cache,
+            "total": index + cache,
+            "max": self.max_memory(),
+        })
+    }
+
+    /// 最早到期的 key 的到期时间
+    pub fn next_expiry(&self) -> Option<i64> {
+        self.state.expiry.lock().unwrap().first().map(|v| v.0)
+    }
+
+    /// 删除已经过期的 key（写入删除标记并释放索引配额），单次最多处理 `limit` 个
+    ///
+    /// 需要持有库写锁调用，避免选出的 key 在删除之前被重新写入。
+    pub async fn sweep_expired(&mut self, limit: usize) -> crate::Result<usize> {
+        let now = chrono::Local::now().timestamp();
+
+        let keys: Vec<String> = self
+            .state
+            .expiry
+            .lock()
+            .unwrap()
+            .iter()
+            .take_while(|(deadline, _)| *deadline < now)
+            .take(limit)
+            .map(|(_, key)| key.clone())
+            .collect();
+
+        let count = keys.len();
+
+        // 删除时写入任务会同步移除到期记录
+        self.delete_many(keys).await?;
+
+        Ok(count)
+    }
+
+    /// 统计数据文件中的无效数据
+    pub async fn fragmentation(&self) -> crate::Result<Fragmentation> {
+        let now = chrono::Local::now().timestamp();
+
+        let mut live_bytes = 0;
+        let mut expired_bytes = 0;
+
+        for info in self.state.index.iter() {
+            let len = info.end_position - info.start_position;
+            live_bytes += len;
+            if is_expired(info.time_stamp, now) {
+                expired_bytes += len;
+            }
+        }
+
+        // 保留的历史版本不算作无效数据
+        live_bytes += self.state.history.lock().unwrap().size();
+
+        Ok(Fragmentation {
+            total_bytes: self.state.storage.data_size().await?,
+            live_bytes,
+            expired_bytes,
+        })
+    }
+
+    pub fn merge_progress(&self) -> Arc<MergeProgress> {
+        self.state.merge.clone()
+    }
+
+    /// 冻结当前数据（持有写锁）：归档活跃文件，并将所有归档文件硬链接到临时目录
+    ///
+    /// 硬链接不复制数据，之后合并替换原文件也不会影响临时目录中的内容。
+    /// 返回临时目录以及快照时的文件编号。
+    async fn freeze(&mut self) -> crate::Result<(PathBuf, u32)> {
+        self.rotate().await?;
+
+        let staging = self.state.root.join(format!(
+            "{}{}",
+            SNAPSHOT_STAGING_PREFIX,
+            chrono::Local::now().timestamp_nanos()
+        ));
+        let root = self.state.root.clone();
+        let target = staging.clone();
+
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&target)?;
+
+            let result = (|| -> crate::Result<()> {
+                for id in archive_ids(&root) {
+                    let name = format!("archive-{}.db", id);
+                    fs::hard_link(root.join(&name), target.join(&name))?;
+
+                    let hint = hint::hint_path(&root, id);
+                    if hint.is_file() {
+                        fs::hard_link(&hint, hint::hint_path(&target, id))?;
+                    }
+                }
+                Ok(())
+            })();
+
+            if result.is_err() {
+                let _ = fs::remove_dir_all(&target);
+            }
+            result
+        })
+        .await??;
+
+        Ok((staging, self.state.file_id()))
+    }
+
+    /// 合并第一阶段（持有写锁）：归档活跃文件，由存储后端记录需要保留的记录
+    async fn prepare_merge(&mut self) -> crate::Result<Option<MergePlan>> {
+        // 归档由写入任务执行，排在已经提交的写入之后
+        self.rotate().await?;
+        self.state.storage.prepare_merge(&self.state)
+    }
+
+    /// 合并第三阶段（持有写锁）：由存储后端替换数据文件并更新合并期间没有被修改过的索引
+    async fn finish_merge(&mut self, plan: &MergePlan, output: MergeOutput) -> crate::Result<()> {
+        self.state.storage.finish_merge(&self.state, plan, output).await
+    }
+
+    /// 合并分为三个阶段：持有写锁记录需要保留的记录，锁外复制，再持有写锁替换数据文件并更新索引
+    async fn merge(db: &RwLock<DataBase>) -> crate::Result<()> {
+        let plan = match db.write().await.prepare_merge().await? {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let plan = Arc::new(plan);
+
+        // 复制阶段不持有库锁，SET / GET 可以正常进行
+        let output = {
+            let plan = plan.clone();
+            tokio::task::spawn_blocking(move || plan.run()).await??
+        };
+
+        db.write().await.finish_merge(&plan, output).await
+    }
+
+    /// 获取当前已写入位置的持久化凭证
+    ///
+    /// 需要在释放库锁之后再等待，这样其他连接的写入可以共享同一次 fsync。
+    pub fn commit_ticket(&self) -> CommitTicket {
+        self.state.commit.ticket()
+    }
+}
+
+impl DataNode {
+    pub(crate) fn new(key: String, value: DataValue, time_stamp: (i64, u64)) -> Self {
+        Self {
+            crc: 0,
+            key,
+            value,
+            time_stamp,
+            crc_version: CRC_RECORD,
+        }
+    }
+
+    pub(crate) fn timestamp(&self) -> (i64, u64) {
+        self.time_stamp
+    }
+
+    /// 校验记录内容是否与写入时的 CRC 一致
+    pub(crate) fn verify(&self) -> Result<()> {
+        let expected = match self.crc_version {
+            CRC_RECORD => return Ok(()),
+            CRC_VERSION => value_checksum(&self.value),
+            _ => {
+                // 旧版本校验码基于 HashMap 遍历顺序，包含字典的值无法复现，只能跳过
+                if contains_dict(&self.value) {
+                    return Ok(());
+                }
+                CASTAGNOLI.checksum(self.value.to_string().as_bytes())
+            }
+        };
+
+        if expected != self.crc {
+            return Err(anyhow!(
+                "checksum mismatch for key `{}` (stored {:08x}, computed {:08x})",
+                self.key,
+                self.crc,
+                expected
+            ));
+        }
+
+        Ok(())
+    }
+    pub(crate) fn weight(self) -> f64 {
+        self.value.weight()
+    }
+}
+
+tokio::task_local! {
+    /// Pipeline 模式下延迟等待持久化：整批命令执行完后统一等待一次
+    pub(crate) static DEFERRED_COMMIT: RefCell<Vec<CommitTicket>>;
+}
+
+/// 组提交（group commit）控制器
+///
+/// 写入序号 `written` 为该库自启动以来写入的总字节数，`synced` 为已经 fsync 的序号。
+/// 多个写入在同一次 fsync 之前到达时，共享这一次 fsync。
+#[derive(Debug)]
+pub(crate) struct GroupCommit {
+    pub(crate) mode: Durability,
+    state: std::sync::Mutex<CommitState>,
+    synced: tokio::sync::watch::Sender<u64>,
+    /// 同一时间只允许一个 fsync，后到达的写入等待它完成后再判断
+    sync_lock: Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct CommitState {
+    written: u64,
+    file: Option<Arc<tokio::fs::File>>,
+}
+
+/// 持久化凭证：`wait` 返回后，凭证之前的所有写入都已满足持久化策略
+#[derive(Debug, Clone)]
+pub struct CommitTicket {
+    commit: Arc<GroupCommit>,
+    target: u64,
+}
+
+impl GroupCommit {
+    fn new(mode: Durability) -> Arc<Self> {
+        let (synced, _) = tokio::sync::watch::channel(0);
+
+        let commit = Arc::new(Self {
+            mode,
+            state: std::sync::Mutex::new(CommitState::default()),
+            synced,
+            sync_lock: Mutex::new(()),
+        });
+
+        if let Durability::Interval(ms) = mode {
+            let weak = Arc::downgrade(&commit);
+            tokio::spawn(GroupCommit::interval_sync(weak, ms));
+        }
+
+        commit
+    }
+
+    /// interval 模式的后台 fsync 任务，库被释放后自动退出
+    async fn interval_sync(commit: Weak<GroupCommit>, ms: u64) {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(ms));
+
+        loop {
+            interval.tick().await;
+
+            let commit = match commit.upgrade() {
+                Some(v) => v,
+                None => break,
+            };
+
+            let written = commit.state.lock().unwrap().written;
+            if *commit.synced.borrow() < written {
+                if let Err(e) = commit.sync_to(written).await {
+                    log::error!("interval fsync failed: {}.", e);
+                }
+            }
+        }
+    }
+
+    fn ticket(self: &Arc<Self>) -> CommitTicket {
+        CommitTicket {
+            commit: self.clone(),
+            target: self.state.lock().unwrap().written,
+        }
+    }
+
+    /// 新打开活跃文件写入器时登记 fsync 所用的句柄
+    pub(crate) fn attach(&self, file: Arc<tokio::fs::File>) {
+        self.state.lock().unwrap().file = Some(file);
+    }
+
+    pub(crate) fn advance(&self, len: u64) {
+        self.state.lock().unwrap().written += len;
+    }
+
+    /// 活跃文件已关闭（归档时已 fsync 或被清空），之前的写入无需再等待
+    pub(crate) fn detach(&self) {
+        let written = {
+            let mut state = self.state.lock().unwrap();
+            state.file = None;
+            state.written
+        };
+        self.publish(written);
+    }
+
+    fn publish(&self, synced: u64) {
+        self.synced.send_if_modified(|v| {
+            if *v < synced {
+                *v = synced;
+                return true;
+            }
+            false
+        });
+    }
+
+    async fn sync_to(&self, target: u64) -> crate::Result<()> {
+        let _guard = self.sync_lock.lock().await;
+
+        if *self.synced.borrow() >= target {
+            return Ok(());
+        }
+
+        // 以 fsync 开始时的写入序号为准，此前写入的数据都会被这一次 fsync 覆盖
+        let (written, file) = {
+            let state = self.state.lock().unwrap();
+            (state.written, state.file.clone())
+        };
+
+        if let Some(file) = file {
+            file.sync_data().await?;
+        }
+
+        self.publish(written);
+
+        Ok(())
+    }
+
+    async fn wait(&self, target: u64) -> crate::Result<()> {
+        match self.mode {
+            Durability::None => Ok(()),
+            Durability::EveryWrite => self.sync_to(target).await,
+            Durability::Interval(_) => {
+                let mut rx = self.synced.subscribe();
+                while *rx.borrow_and_update() < target {
+                    rx.changed().await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl CommitTicket {
+    pub async fn wait(self) -> crate::Result<()> {
+        if self.commit.mode == Durability::None {
+            return Ok(());
+        }
+
+        // 处于 Pipeline 批处理中时只登记凭证，由批处理结束时统一等待
+        let mut ticket = Some(self);
+        let _ = DEFERRED_COMMIT.try_with(|v| v.borrow_mut().push(ticket.take().unwrap()));
+
+        match ticket {
+            Some(ticket) => ticket.commit.wait(ticket.target).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// 库的共享状态：读取直接访问，修改由写入任务进行（合并完成时持有库写锁更新）
+///
+/// 同时需要多个锁时按 index -> history -> expiry 的顺序获取，
+/// 并且不在持有 `index` 的引用时获取其他锁。
+#[derive(Debug)]
+pub(crate) struct GroupState {
+    pub(crate) root: PathBuf,
+    pub(crate) index: DashMap<String, IndexInfo>,
+    /// 设置了过期时间的 key，按到期时间排序 (deadline, key)
+    pub(crate) expiry: std::sync::Mutex<BTreeSet<(i64, String)>>,
+    /// 被覆盖或删除的历史版本
+    pub(crate) history: std::sync::Mutex<History>,
+    /// 活跃文件编号（与 record.in 一致，归档时更新）
+    pub(crate) file_id: AtomicU32,
+    /// 记录的存储后端
+    pub(crate) storage: Arc<dyn StorageBackend>,
+    /// 已解码数据的缓存
+    pub(crate) cache: ValueCache,
+    /// 新写入记录的压缩设置（修改单库配置时更新）
+    codec: std::sync::Mutex<Codec>,
+    /// fsync 策略与组提交状态
+    pub(crate) commit: Arc<GroupCommit>,
+    /// 后台合并进度
+    pub(crate) merge: Arc<MergeProgress>,
+    /// 数据文件被整体替换（合并）的次数，用于判断合并期间文件是否已被替换
+    pub(crate) epoch: AtomicU64,
+    /// 索引与历史版本占用的内存（字节）
+    memory: AtomicU64,
+    /// 同一时间只有一个写入执行淘汰
+    evicting: Mutex<()>,
+    /// 已淘汰的 key 数量
+    evicted: AtomicU64,
+}
+
+impl Drop for GroupState {
+    fn drop(&mut self) {
+        // 库被卸载（写入任务退出）后归还索引占用的全局内存
+        crate::memory::charge_index(-(*self.memory.get_mut() as i64));
+    }
+}
+
+impl GroupState {
+    pub(crate) fn file_id(&self) -> u32 {
+        self.file_id.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn codec(&self) -> Codec {
+        *self.codec.lock().unwrap()
+    }
+
+    /// 当前已过期的 key 及其索引
+    pub(crate) fn expired(&self, now: i64) -> Vec<(String, IndexInfo)> {
+        let keys: Vec<String> = self
+            .expiry
+            .lock()
+            .unwrap()
+            .iter()
+            .take_while(|(deadline, _)| *deadline < now)
+            .map(|(_, key)| key.clone())
+            .collect();
+
+        keys.into_iter()
+            .filter_map(|key| self.index.get(&key).map(|v| v.clone()).map(|v| (key, v)))
+            .collect()
+    }
+
+    /// 移除已过期且仍然指向 `old` 的 key（不写入删除标记），返回它引用过的记录的位置
+    pub(crate) fn remove_expired(&self, key: &str, old: &IndexInfo) -> Option<Vec<(u64, u64)>> {
+        self.index.remove_if(key, |_, v| v == old)?;
+
+        let mut records = vec![(old.start_position, old.end_position)];
+        let released = {
+            let mut history = self.history.lock().unwrap();
+            records.extend(history.get(key).map(|v| (v.info.start_position, v.info.end_position)));
+            history.remove(key)
+        };
+
+        self.charge(-((index_entry_size(key) + released) as i64));
+        if let Some(deadline) = expire_deadline(old.time_stamp) {
+            self.expiry.lock().unwrap().remove(&(deadline, key.to_string()));
+        }
+        self.cache.remove(key);
+        TOTAL_INDEX_NUMBER.fetch_sub(1, Ordering::Relaxed);
+
+        Some(records)
+    }
+
+    /// 调整索引占用的内存，同时计入全局统计
+    pub(crate) fn charge(&self, bytes: i64) {
+        if bytes >= 0 {
+            self.memory.fetch_add(bytes as u64, Ordering::Relaxed);
+        } else {
+            self.memory.fetch_sub(bytes.unsigned_abs(), Ordering::Relaxed);
+        }
+        crate::memory::charge_index(bytes);
+    }
+
+    async fn read(self: &Arc<Self>, info: IndexInfo) -> crate::Result<DataNode> {
+        self.storage.read(self, info).await
+    }
+}
+
+// 写入请求队列长度
+const WRITE_QUEUE_SIZE: usize = 1024;
+
+// 单次追加最多合并的写入数量
+const WRITE_BATCH_SIZE: usize = 256;
+
+/// 写入记录的类型
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WriteKind {
+    Set,
+    /// 移除索引，当前值成为最新的历史版本
+    Delete,
+    /// 淘汰：移除索引并丢弃该 key 的历史版本以释放内存
+    ///
+    /// 只在索引仍然是选出时的值时执行，否则跳过且不写入删除标记。
+    Evict(IndexInfo),
+}
+
+/// 写入任务收到的请求
+enum WriteOp {
+    /// 写入一条记录
+    Put {
+        node: DataNode,
+        kind: WriteKind,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    /// 归档活跃文件
+    Rotate { reply: oneshot::Sender<Result<()>> },
+    /// 将数据文件复制到另一个目录
+    Fork {
+        target: PathBuf,
+        reply: oneshot::Sender<Result<()>>,
+    },
+}
+
+impl std::fmt::Debug for WriteOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteOp::Put { node, kind, .. } => f
+                .debug_struct("Put")
+                .field("key", &node.key)
+                .field("kind", kind)
+                .finish_non_exhaustive(),
+            WriteOp::Rotate { .. } => f.write_str("Rotate"),
+            WriteOp::Fork { target, .. } => f.debug_struct("Fork").field("target", target).finish_non_exhaustive(),
+        }
+    }
+}
+
+/// 等待写入的记录
+type PendingWrite = (DataNode, WriteKind, oneshot::Sender<Result<()>>);
+
+/// 库的写入端，加载完成后移入写入任务，所有修改都由它按顺序交给存储后端执行
+#[derive(Debug)]
+struct DataFile {
+    name: String,
+    state: Arc<GroupState>,
+}
+
+impl DataFile {
+    pub fn new(
+        root: &Path,
+        name: String,
+        storage: Arc<dyn StorageBackend>,
+        durability: Durability,
+        codec: Codec,
+        history_depth: usize,
+        cache_size: u64,
+    ) -> Self {
+        let state = GroupState {
+            root: root.to_path_buf(),
+            index: DashMap::new(),
+            expiry: std::sync::Mutex::new(BTreeSet::new()),
+            history: std::sync::Mutex::new(History::new(history_depth)),
+            file_id: AtomicU32::new(1),
+            storage,
+            cache: ValueCache::new(cache_size),
+            codec: std::sync::Mutex::new(codec),
+            commit: GroupCommit::new(durability),
+            merge: Arc::new(MergeProgress::default()),
+            epoch: AtomicU64::new(0),
+            memory: AtomicU64::new(0),
+            evicting: Mutex::new(()),
+            evicted: AtomicU64::new(0),
+        };
+
+        Self {
+            name,
+            state: Arc::new(state),
+        }
+    }
+
+    /// 加载索引（阻塞操作，需要在阻塞线程池中调用）
+    pub fn load_index(&mut self, progress: &LoadProgress) -> crate::Result<()> {
+        self.state.storage.load_index(&self.state, progress)
+    }
+
+    /// 写入任务：按顺序处理写入请求，已经在队列中的写入合并为一次追加
+    async fn run(mut self, mut queue: mpsc::Receiver<WriteOp>) {
+        let mut batch: Vec<PendingWrite> = Vec::with_capacity(WRITE_BATCH_SIZE);
+
+        while let Some(op) = queue.recv().await {
+            let mut control = None;
+
+            match op {
+                WriteOp::Put { node, kind, reply } => batch.push((node, kind, reply)),
+                op => control = Some(op),
+            }
+
+            // 归档与复制需要在它之前提交的写入完成之后进行
+            while control.is_none() && batch.len() < WRITE_BATCH_SIZE {
+                match queue.try_recv() {
+                    Ok(WriteOp::Put { node, kind, reply }) => batch.push((node, kind, reply)),
+                    Ok(op) => control = Some(op),
+                    Err(_) => break,
+                }
+            }
+
+            if !batch.is_empty() {
+                self.write_batch(&mut batch).await;
+            }
+
+            match control {
+                Some(WriteOp::Rotate { reply }) => {
+                    let _ = reply.send(self.state.storage.rotate(&self.state).await);
+                }
+                Some(WriteOp::Fork { target, reply }) => {
+                    let _ = reply.send(self.state.storage.fork(&self.state, target).await);
+                }
+                _ => {}
+            }
+        }
+
+        log::debug!("writer of @{} stopped.", self.name);
+    }
+
+    /// 将一批记录交给存储后端，全部写入成功后再更新索引
+    async fn write_batch(&mut self, batch: &mut Vec<PendingWrite>) {
+        self.skip_stale_evictions(batch);
+        if batch.is_empty() {
+            return;
+        }
+
+        let result = {
+            let nodes: Vec<&DataNode> = batch.iter().map(|v| &v.0).collect();
+            self.state.storage.write(&self.state, &nodes).await
+        };
+
+        match result {
+            Ok(positions) => {
+                for ((node, kind, reply), (start, end)) in batch.drain(..).zip(positions) {
+                    self.apply(node, kind, start, end);
+                    let _ = reply.send(Ok(()));
+                }
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                for (_, _, reply) in batch.drain(..) {
+                    let _ = reply.send(Err(anyhow!("{}", reason)));
+                }
+            }
+        }
+    }
+
+    /// 跳过选出之后 key 已经被修改（包括同一批中排在前面的写入）的淘汰，直接返回成功
+    fn skip_stale_evictions(&self, batch: &mut Vec<PendingWrite>) {
+        let mut touched = HashSet::new();
+
+        for (node, kind, reply) in std::mem::take(batch) {
+            let fresh = !touched.contains(&node.key);
+            touched.insert(node.key.clone());
+
+            if let WriteKind::Evict(old) = &kind {
+                let current = self.state.index.get(&node.key).map(|v| v.clone());
+                if !fresh || current.as_ref() != Some(old) {
+                    log::debug!("@{} skipped eviction of modified key `{}`.", self.name, node.key);
+                    let _ = reply.send(Ok(()));
+                    continue;
+                }
+            }
+
+            batch.push((node, kind, reply));
+        }
+    }
+
+    /// 更新已写入记录的索引、历史版本与到期时间
+    fn apply(&mut self, node: DataNode, kind: WriteKind, start_position: u64, end_position: u64) {
+        let info = IndexInfo {
+            file_id: self.state.file_id(),
+            start_position,
+            end_position,
+            time_stamp: node.time_stamp,
+            access: Access::new(node.time_stamp.0),
+        };
+
+        let delete = kind != WriteKind::Set;
+
+        let key = node.key;
+
+        self.state.cache.remove(&key);
+
+        let previous = if let WriteKind::Evict(old) = &kind {
+            self.state.index.remove_if(&key, |_, v| v == old).map(|v| v.1)
+        } else if delete {
+            self.state.index.remove(&key).map(|v| v.1)
+        } else {
+            self.state.index.insert(key.clone(), info.clone())
+        };
+
+        let mut history = self.state.history.lock().unwrap();
+        let mut expiry = self.state.expiry.lock().unwrap();
+
+        // 内存存储在记录不再被索引与历史版本引用时立即释放，这里先记下该 key 引用过的记录
+        let reclaim = self.state.storage.kind() == StorageKind::Memory;
+        let mut released: Vec<(u64, u64)> = vec![];
+        if reclaim {
+            released.push((start_position, end_position));
+            released.extend(
+                previous
+                    .iter()
+                    .chain(history.get(&key).map(|v| &v.info))
+                    .map(|v| (v.start_position, v.end_position)),
+            );
+        }
+
+        let mut charge = -(history.memory as i64);
+
+        let evicted = matches!(kind, WriteKind::Evict(_)) && previous.is_some();
+        if evicted {
+            self.state.evicted.fetch_add(1, Ordering::Relaxed);
+        }
+
+        match previous {
+            Some(previous) => {
+                if let Some(deadline) = expire_deadline(previous.time_stamp) {
+                    expiry.remove(&(deadline, key.clone()));
+                }
+                if !evicted {
+                    history.push(&key, previous, false);
+                }
+                if delete {
+                    TOTAL_INDEX_NUMBER.fetch_sub(1, Ordering::Relaxed);
+                    charge -= index_entry_size(&key) as i64;
+                }
+            }
+            None if !delete => {
+                TOTAL_INDEX_NUMBER.fetch_add(1, Ordering::Relaxed);
+                charge += index_entry_size(&key) as i64;
+            }
+            None => {}
+        }
+
+        if evicted {
+            history.remove(&key);
+        } else if delete {
+            // 删除标记取代当前值成为最新的历史版本
+            history.push(&key, info, true);
+        } else if let Some(deadline) = expire_deadline(info.time_stamp) {
+            expiry.insert((deadline, key.clone()));
+        }
+
+        charge += history.memory as i64;
+        self.state.charge(charge);
+
+        if reclaim {
+            let live: HashSet<u64> = history
+                .get(&key)
+                .map(|v| v.info.start_position)
+                .chain((!delete).then_some(start_position))
+                .collect();
+            released.retain(|v| !live.contains(&v.0));
+
+            drop(expiry);
+            drop(history);
+            self.state.storage.release(&self.state, &released);
+        }
+    }
+}
+
+/// 读取库目录中的单库配置，文件不存在时使用默认配置
+fn load_group_config(root: &Path) -> crate::Result<GroupConfig> {
+    match fs::read_to_string(root.join(GROUP_CONFIG_FILE)) {
+        Ok(v) => Ok(serde_json::from_str(&v)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(GroupConfig::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn save_group_config(root: &Path, config: &GroupConfig) -> crate::Result<()> {
+    write_atomic(root, GROUP_CONFIG_FILE, serde_json::to_string(config)?.as_bytes())
+}
+
+/// 写入临时文件并落盘后重命名为 `name`，保证文件内容要么是旧的、要么是完整的新内容
+pub(crate) fn write_atomic(root: &Path, name: &str, data: &[u8]) -> crate::Result<()> {
+    let temp = root.join(format!("{}.tmp", name));
+
+    let mut f = fs::File::create(&temp)?;
+    f.write_all(data)?;
+    f.sync_all()?;
+
+    rename(&temp, root.join(name))?;
+
+    // 目录项落盘（部分平台不支持打开目录，忽略失败）
+    if let Ok(dir) = fs::File::open(root) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// 数据文件的碎片统计
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Fragmentation {
+    /// 所有数据文件中记录的总字节数
+    total_bytes: u64,
+    /// 索引指向的记录字节数
+    live_bytes: u64,
+    /// 已过期但仍在索引中的记录字节数
+    expired_bytes: u64,
+}
+
+impl Fragmentation {
+    pub fn dead_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.live_bytes) + self.expired_bytes
+    }
+
+    pub fn dead_ratio(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        self.dead_bytes() as f64 / self.total_bytes as f64
+    }
+}
+
+/// 后台合并进度
+#[derive(Debug, Default)]
+pub struct MergeProgress {
+    running: AtomicBool,
+    total_bytes: AtomicU64,
+    /// 已处理（复制或因过期跳过）的字节数
+    pub(crate) scanned_bytes: AtomicU64,
+    pub(crate) copied_bytes: AtomicU64,
+    started_at: AtomicI64,
+    /// 最近一次合并的结果（`ok` 或错误信息）
+    last_result: std::sync::Mutex<Option<String>>,
+}
+
+impl MergeProgress {
+    /// 标记合并开始，已有合并在运行时返回 false
+    fn try_start(&self) -> bool {
+        self.running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    pub(crate) fn begin(&self, total_bytes: u64) {
+        self.total_bytes.store(total_bytes, Ordering::Relaxed);
+        self.scanned_bytes.store(0, Ordering::Relaxed);
+        self.copied_bytes.store(0, Ordering::Relaxed);
+    }
+
+    fn finish(&self, result: &crate::Result<()>) {
+        *self.last_result.lock().unwrap() = Some(match result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        });
+        self.running.store(false, Ordering::Release);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Acquire)
+    }
+
+    pub fn report(&self, group: &str) -> serde_json::Value {
+        let total = self.total_bytes.load(Ordering::Relaxed);
+        let scanned = self.scanned_bytes.load(Ordering::Relaxed);
+
+        json!({
+            "group": group,
+            "running": self.is_running(),
+            "started_at": self.started_at.load(Ordering::Relaxed),
+            "total_bytes": total,
+            "scanned_bytes": scanned,
+            "copied_bytes": self.copied_bytes.load(Ordering::Relaxed),
+            "progress": if total == 0 { 100.0 } else { scanned as f64 * 100.0 / total as f64 },
+            "last_result": self.last_result.lock().unwrap().clone(),
+        })
+    }
+}
+
+/// 同一文件内每个 key 只保留最后 `keep` 条记录（保持记录在文件中的先后顺序）
+pub(crate) fn compact_entries(entries: Vec<HintEntry>, keep: usize) -> Vec<HintEntry> {
+    let mut latest: HashMap<String, VecDeque<HintEntry>> = HashMap::with_capacity(entries.len());
+
+    for entry in entries {
+        let list = latest.entry(entry.key.clone()).or_default();
+        list.push_back(entry);
+        if list.len() > keep {
+            list.pop_front();
+        }
+    }
+
+    let mut result: Vec<HintEntry> = latest.into_values().flatten().collect();
+    result.sort_by_key(|v| v.start_position);
+    result
+}
+
+/// 将某个数据文件中的记录应用到索引上，被覆盖或删除的版本进入历史记录
+pub(crate) fn apply_entries(
+    index: &mut HashMap<String, IndexInfo>,
+    history: &mut History,
+    file_id: u32,
+    entries: Vec<HintEntry>,
+) {
+    for entry in entries {
+        let info = IndexInfo {
+            file_id,
+            start_position: entry.start_position,
+            end_position: entry.end_position,
+            time_stamp: entry.time_stamp,
+            access: Access::new(entry.time_stamp.0),
+        };
+
+        if let Some(previous) = index.remove(&entry.key) {
+            history.push(&entry.key, previous, false);
+        }
+
+        if entry.tombstone {
+            history.push(&entry.key, info, true);
+        } else {
+            index.insert(entry.key, info);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct IndexInfo {
+    pub(crate) file_id: u32,
+    pub(crate) start_position: u64,
+    pub(crate) end_position: u64,
+    pub(crate) time_stamp: (i64, u64),
+    #[serde(skip)]
+    pub(crate) access: Access,
+}
+
+// 访问记录不影响索引是否指向同一条记录
+impl PartialEq for IndexInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.file_id == other.file_id
+            && self.start_position == other.start_position
+            && self.end_position == other.end_position
+            && self.time_stamp == other.time_stamp
+    }
+}
+
+impl Eq for IndexInfo {}
+
+/// key 的访问记录，用于库内 key 的淘汰（只保存在内存中，写入或重新加载后从写入时间开始）
+#[derive(Debug, Default)]
+pub(crate) struct Access {
+    /// 最近一次访问时间
+    last: AtomicI64,
+    /// 访问次数
+    count: AtomicU32,
+}
+
+impl Access {
+    pub(crate) fn new(now: i64) -> Self {
+        Self {
+            last: AtomicI64::new(now),
+            count: AtomicU32::new(1),
+        }
+    }
+
+    fn touch(&self, now: i64) {
+        self.last.store(now, Ordering::Relaxed);
+        let _ = self
+            .count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1));
+    }
+
+    fn last(&self) -> i64 {
+        self.last.load(Ordering::Relaxed)
+    }
+
+    /// 访问频率：每隔 `LFU_DECAY_TIME` 未被访问减半
+    fn frequency(&self, now: i64) -> u32 {
+        let idle = (now - self.last()).max(0) / LFU_DECAY_TIME;
+        self.count.load(Ordering::Relaxed) >> idle.min(31)
+    }
+}
+
+impl Clone for Access {
+    fn clone(&self) -> Self {
+        Self {
+            last: AtomicI64::new(self.last()),
+            count: AtomicU32::new(self.count.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// key 被覆盖或删除之前的版本
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Version {
+    pub(crate) info: IndexInfo,
+    /// 该版本是删除标记
+    pub(crate) tombstone: bool,
+}
+
+/// 保留每个 key 最近 `depth` 个历史版本（不含当前值，新版本在前）
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    pub(crate) depth: usize,
+    versions: HashMap<String, VecDeque<Version>>,
+    /// 占用的内存（字节）
+    pub(crate) memory: u64,
+}
+
+impl History {
+    fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            versions: HashMap::new(),
+            memory: 0,
+        }
+    }
+
+    fn push(&mut self, key: &str, info: IndexInfo, tombstone: bool) {
+        if self.depth == 0 {
+            return;
+        }
+
+        if !self.versions.contains_key(key) {
+            self.memory += index_entry_size(key);
+        }
+
+        let list = self.versions.entry(key.to_string()).or_default();
+        list.push_front(Version { info, tombstone });
+        self.memory += VERSION_OVERHEAD;
+
+        if list.len() > self.depth {
+            list.truncate(self.depth);
+            self.memory -= VERSION_OVERHEAD;
+        }
+    }
+
+    fn get(&self, key: &str) -> impl Iterator<Item = &Version> {
+        self.versions.get(key).into_iter().flatten()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &Version)> {
+        self.versions
+            .iter()
+            .flat_map(|(key, list)| list.iter().map(move |v| (key, v)))
+    }
+
+    /// 合并后更新被移动的历史版本位置
+    pub(crate) fn remap(&mut self, key: &str, old: &IndexInfo, new: &IndexInfo) {
+        if let Some(list) = self.versions.get_mut(key) {
+            for version in list.iter_mut() {
+                if &version.info == old {
+                    version.info = new.clone();
+                }
+            }
+        }
+    }
+
+    /// 移除 key 的所有历史版本，返回释放的内存
+    fn remove(&mut self, key: &str) -> u64 {
+        let released = match self.versions.remove(key) {
+            Some(list) => index_entry_size(key) + list.len() as u64 * VERSION_OVERHEAD,
+            None => 0,
+        };
+        self.memory -= released;
+        released
+    }
+
+    fn size(&self) -> u64 {
+        self.iter()
+            .map(|(_, v)| v.info.end_position - v.info.start_position)
+            .sum()
+    }
+}
+
+pub async fn total_index_number() -> u32 {
+    TOTAL_INDEX_NUMBER.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::NetPacketState;
+    use crate::storage::log::{MERGE_DIR, MERGE_MARKER};
+    use futures::future::BoxFuture;
+
+    /// 测试使用的库目录（每个测试使用不同的名称，已存在时先清空）
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("dorea-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        root
+    }
+
+    fn test_config(extra: &str) -> DataBaseConfig {
+        toml::from_str(&format!(
+            "default_group = \"default\"\npre_load_group = []\n{}",
+            extra
+        ))
+        .unwrap()
+    }
+
+    async fn open(root: &Path, name: &str, config: DataBaseConfig) -> DataBase {
+        DataBase::init(name.into(), root.to_path_buf(), config, Arc::default())
+            .await
+            .unwrap()
+    }
+
+    /// 在 `root` 下创建一个不预加载任何库的管理器
+    async fn manager(root: &Path, extra: &str) -> Arc<DataBaseManager> {
+        fs::create_dir_all(root).unwrap();
+        fs::write(
+            root.join("config.toml"),
+            format!(
+                "[connection]\nmax_connect_number = 255\nconnection_password = \"\"\n\n\
+                 [database]\ndefault_group = \"default\"\npre_load_group = []\n{}",
+                extra
+            ),
+        )
+        .unwrap();
+
+        Arc::new(DataBaseManager::new(root.to_path_buf()).await)
+    }
+
+    fn string(v: &str) -> DataValue {
+        DataValue::String(v.into())
+    }
+
+    /// 使用指定存储后端的库，与 `DataBase::init` 一样启动写入任务
+    fn open_with(name: &str, storage: Arc<dyn StorageBackend>) -> DataBase {
+        let data_file = DataFile::new(
+            &std::env::temp_dir().join(name),
+            name.into(),
+            storage,
+            Durability::None,
+            Codec::default(),
+            2,
+            0,
+        );
+
+        let (writer, queue) = mpsc::channel(WRITE_QUEUE_SIZE);
+        let state = data_file.state.clone();
+        tokio::spawn(data_file.run(queue));
+
+        DataBase {
+            name: name.into(),
+            timestamp: chrono::Local::now().timestamp(),
+            location: state.root.clone(),
+            state,
+            writer,
+            eviction: EvictionPolicy::default(),
+            config: GroupConfig::default(),
+        }
+    }
+
+    /// 记录每次追加的记录数量，持有 `gate` 时写入任务停在追加之前
+    #[derive(Debug, Default)]
+    struct GatedStorage {
+        inner: MemoryStorage,
+        batches: std::sync::Mutex<Vec<usize>>,
+        gate: Mutex<()>,
+    }
+
+    impl StorageBackend for GatedStorage {
+        fn kind(&self) -> StorageKind {
+            self.inner.kind()
+        }
+
+        fn load_index(&self, state: &GroupState, progress: &LoadProgress) -> crate::Result<()> {
+            self.inner.load_index(state, progress)
+        }
+
+        fn write<'a>(
+            &'a self,
+            state: &'a GroupState,
+            nodes: &'a [&'a DataNode],
+        ) -> BoxFuture<'a, crate::Result<Vec<(u64, u64)>>> {
+            Box::pin(async move {
+                self.batches.lock().unwrap().push(nodes.len());
+                let _gate = self.gate.lock().await;
+                self.inner.write(state, nodes).await
+            })
+        }
+
+        fn read(&self, state: &Arc<GroupState>, info: IndexInfo) -> BoxFuture<'static, crate::Result<DataNode>> {
+            self.inner.read(state, info)
+        }
+
+        fn prepare_merge(&self, state: &GroupState) -> crate::Result<Option<MergePlan>> {
+            self.inner.prepare_merge(state)
+        }
+
+        fn clean(&self) -> BoxFuture<'_, crate::Result<()>> {
+            self.inner.clean()
+        }
+
+        fn data_size(&self) -> BoxFuture<'_, crate::Result<u64>> {
+            self.inner.data_size()
+        }
+
+        fn release(&self, state: &GroupState, records: &[(u64, u64)]) {
+            self.inner.release(state, records)
+        }
+    }
+
+    fn node(value: DataValue) -> DataNode {
+        DataNode {
+            crc: value_checksum(&value),
+            key: "foo".into(),
+            value,
+            time_stamp: (0, 0),
+            crc_version: CRC_VERSION,
+        }
+    }
+
+    #[test]
+    fn test_checksum_verify() {
+        let mut dict = HashMap::new();
+        for i in 0..32 {
+            dict.insert(format!("k{}", i), DataValue::Number(i as f64));
+        }
+
+        // 反序列化后字典的遍历顺序会变化，校验码不能受其影响
+        let origin = node(DataValue::Dict(dict));
+        let decoded: DataNode =
+            serde_json::from_slice(&serde_json::to_vec(&origin).unwrap()).unwrap();
+        assert!(decoded.verify().is_ok());
+
+        let mut broken = node(DataValue::String("hello".into()));
+        broken.value = DataValue::String("jello".into());
+        assert!(broken.verify().is_err());
+    }
+
+    #[test]
+    fn test_history_from_entries() {
+        let entry = |start: u64, tombstone: bool| HintEntry {
+            key: "foo".into(),
+            start_position: start,
+            end_position: start + 10,
+            time_stamp: (start as i64, 0),
+            tombstone,
+        };
+
+        let entries = vec![entry(16, false), entry(26, false), entry(36, true), entry(46, false)];
+
+        // 每个 key 保留 depth + 1 条记录
+        let compacted = compact_entries(entries.clone(), 3);
+        assert_eq!(compacted, entries[1..].to_vec());
+
+        let mut index = HashMap::new();
+        let mut history = History::new(2);
+        apply_entries(&mut index, &mut history, 1, entries);
+
+        assert_eq!(index.get("foo").map(|v| v.start_position), Some(46));
+        let versions: Vec<(u64, bool)> = history
+            .get("foo")
+            .map(|v| (v.info.start_position, v.tombstone))
+            .collect();
+        assert_eq!(versions, vec![(36, true), (26, false)]);
+
+        // 超出深度被截断的版本不再计入内存
+        assert_eq!(history.memory, index_entry_size("foo") + 2 * VERSION_OVERHEAD);
+        assert_eq!(history.remove("foo"), index_entry_size("foo") + 2 * VERSION_OVERHEAD);
+        assert_eq!(history.memory, 0);
+    }
+
+    #[test]
+    fn test_access_frequency() {
+        let access = Access::new(0);
+        for _ in 0..7 {
+            access.touch(100);
+        }
+        assert_eq!(access.frequency(100), 8);
+
+        // 每隔 LFU_DECAY_TIME 未被访问减半
+        assert_eq!(access.frequency(100 + LFU_DECAY_TIME), 4);
+        assert_eq!(access.frequency(100 + LFU_DECAY_TIME * 3), 1);
+        assert_eq!(access.clone().last(), 100);
+    }
+
+    #[test]
+    fn test_group_commit() {
+        let root = temp_root("commit");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            // every_write：写入返回前已经 fsync
+            let db = open(&root, "every", test_config("durability = \"every_write\"")).await;
+            db.set("foo", string("bar"), 0).await.unwrap();
+            let ticket = db.commit_ticket();
+            let target = ticket.target;
+            assert!(target > 0);
+            ticket.wait().await.unwrap();
+            assert!(*db.state.commit.synced.borrow() >= target);
+
+            // Pipeline 中的写入只登记凭证，批处理结束时第一次等待的 fsync 覆盖整批写入
+            let tickets = DEFERRED_COMMIT
+                .scope(RefCell::new(vec![]), async {
+                    for i in 0..8 {
+                        db.set(&format!("k{}", i), string("v"), 0).await.unwrap();
+                        db.commit_ticket().wait().await.unwrap();
+                    }
+                    DEFERRED_COMMIT.with(|v| v.take())
+                })
+                .await;
+            assert_eq!(tickets.len(), 8);
+            assert_eq!(*db.state.commit.synced.borrow(), target);
+
+            let written = db.state.commit.state.lock().unwrap().written;
+            tickets[0].clone().wait().await.unwrap();
+            assert_eq!(*db.state.commit.synced.borrow(), written);
+
+            // interval：写入等待所在批次的 fsync，这里的间隔足够长，只有归档时的 fsync 能让它返回
+            let db = open(&root, "interval", test_config("durability = \"interval(60000)\"")).await;
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+            db.set("foo", string("bar"), 0).await.unwrap();
+            let waiting = tokio::spawn(db.commit_ticket().wait());
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            assert!(!waiting.is_finished());
+
+            db.rotate().await.unwrap();
+            tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+
+            // 间隔较短时同时到达的写入共享后台的 fsync
+            let db = Arc::new(open(&root, "short", test_config("durability = \"interval(20)\"")).await);
+            let writes: Vec<_> = (0..8)
+                .map(|i| {
+                    let db = db.clone();
+                    tokio::spawn(async move {
+                        db.set(&format!("k{}", i), string("v"), 0).await?;
+                        db.commit_ticket().wait().await
+                    })
+                })
+                .collect();
+            for write in writes {
+                write.await.unwrap().unwrap();
+            }
+            let written = db.state.commit.state.lock().unwrap().written;
+            assert!(*db.state.commit.synced.borrow() >= written);
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_writer_batch() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let storage = Arc::new(GatedStorage::default());
+            let db = Arc::new(open_with("writer", storage.clone()));
+            db.set("a", string("0"), 0).await.unwrap();
+
+            // 写入任务停在第二次追加中
+            let gate = storage.gate.lock().await;
+            let first = tokio::spawn({
+                let db = db.clone();
+                async move { db.set("a", string("1"), 0).await }
+            });
+            while storage.batches.lock().unwrap().len() < 2 {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+
+            // 这期间提交的写入在队列中等待
+            let now = chrono::Local::now().timestamp();
+            let mut pending = vec![];
+            for i in 2..=100 {
+                let node = DataNode::new("a".into(), string(&i.to_string()), (now, 0));
+                pending.push(db.submit(node, WriteKind::Set).await.unwrap());
+            }
+            for i in 0..50 {
+                let node = DataNode::new(format!("k{}", i), string("v"), (now, 0));
+                pending.push(db.submit(node, WriteKind::Set).await.unwrap());
+            }
+
+            // 读取不经过写入任务，不会被排队的写入阻塞
+            let read = tokio::time::timeout(std::time::Duration::from_secs(1), db.get("a")).await;
+            assert_eq!(read.unwrap().unwrap(), Some(string("0")));
+
+            drop(gate);
+            first.await.unwrap().unwrap();
+            for done in pending {
+                done.await.unwrap();
+            }
+
+            // 排队的写入合并为一次追加，并且按提交顺序生效
+            assert_eq!(*storage.batches.lock().unwrap(), vec![1, 1, 149]);
+            assert_eq!(db.get("a").await.unwrap(), Some(string("100")));
+            assert_eq!(db.size(), 51);
+        });
+    }
+
+    #[test]
+    fn test_evict_modified_key() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let storage = Arc::new(GatedStorage::default());
+            let db = Arc::new(open_with("evict-race", storage.clone()));
+            db.set("a", string("1"), 0).await.unwrap();
+            db.set("c", string("1"), 0).await.unwrap();
+            let selected = db.index_info("a").unwrap();
+
+            // 写入任务停在追加中，之后的写入与淘汰进入同一批
+            let gate = storage.gate.lock().await;
+            let blocked = tokio::spawn({
+                let db = db.clone();
+                async move { db.set("b", string("1"), 0).await }
+            });
+            while storage.batches.lock().unwrap().len() < 3 {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+
+            // 淘汰选出 a 之后，另一个连接重新写入了 a
+            let now = chrono::Local::now().timestamp();
+            let set = DataNode::new("a".into(), string("2"), (now, 0));
+            let pending = vec![
+                db.submit(set, WriteKind::Set).await.unwrap(),
+                db.submit(DataNode::new("a".into(), DataValue::None, (now, 0)), WriteKind::Evict(selected.clone()))
+                    .await
+                    .unwrap(),
+                db.submit(
+                    DataNode::new("c".into(), DataValue::None, (now, 0)),
+                    WriteKind::Evict(db.index_info("c").unwrap()),
+                )
+                .await
+                .unwrap(),
+            ];
+
+            drop(gate);
+            blocked.await.unwrap().unwrap();
+            for done in pending {
+                done.await.unwrap();
+            }
+
+            // 被修改的 key 保留新值与历史版本，也没有写入删除标记
+            assert_eq!(*storage.batches.lock().unwrap(), vec![1, 1, 1, 2]);
+            assert_eq!(db.get("a").await.unwrap(), Some(string("2")));
+            assert_eq!(db.history("a", 10).await.unwrap().len(), 2);
+            assert_eq!(db.get("c").await.unwrap(), None);
+            assert_eq!(db.eviction_report()["evicted"], 1);
+
+            // 在之前的批次中被修改过的 key 同样跳过
+            let stale = DataNode::new("a".into(), DataValue::None, (now, 0));
+            db.submit(stale, WriteKind::Evict(selected)).await.unwrap().await.unwrap();
+            assert_eq!(storage.batches.lock().unwrap().len(), 4);
+            assert_eq!(db.get("a").await.unwrap(), Some(string("2")));
+            assert_eq!(db.eviction_report()["evicted"], 1);
+        });
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let root = temp_root("memory");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            manager.select_to("mem-a").await.unwrap();
+            let db = manager.db_list.get("mem-a").unwrap().value().clone();
+
+            {
+                let mut db = db.write().await;
+
+                // 索引按 key 的字节数计入内存，覆盖不变，删除后归还
+                let keys: Vec<String> = (0..100).map(|i| format!("key-{}", i)).collect();
+                for key in keys.iter() {
+                    db.set(key, string("v"), 0).await.unwrap();
+                }
+                let memory: u64 = keys.iter().map(|v| index_entry_size(v)).sum();
+                assert_eq!(db.memory(), memory);
+
+                db.set("key-0", string("value"), 0).await.unwrap();
+                assert_eq!(db.memory(), memory);
+
+                db.delete("key-0").await.unwrap();
+                assert_eq!(db.memory(), memory - index_entry_size("key-0"));
+
+                let report = db.memory_report();
+                assert_eq!(report["index"], json!(db.memory()));
+                assert_eq!(report["cache"], json!(0));
+                assert_eq!(report["total"], json!(db.memory()));
+                assert_eq!(report["max"], json!(crate::memory::max() / MEMORY_PROPORTION_FOR_DB));
+
+                // 单库上限只限制新的 key，已有的 key 仍然可以修改
+                let mut config = db.config().clone();
+                config.max_memory = db.memory() + index_entry_size("new") - 1;
+                db.set_config(config).unwrap();
+                assert_eq!(db.memory_report()["max"], json!(db.config().max_memory));
+
+                let error = db.set("new", string("v"), 0).await.unwrap_err();
+                assert_eq!(error.to_string(), "exceeded group max memory");
+                db.set("key-1", string("value"), 0).await.unwrap();
+
+                let mut config = db.config().clone();
+                config.max_memory = 0;
+                db.set_config(config).unwrap();
+                db.set("new", string("v"), 0).await.unwrap();
+            }
+
+            for name in ["mem-b", "mem-in-use", "mem-locked", "mem-empty"] {
+                manager.select_to(name).await.unwrap();
+                if name != "mem-empty" {
+                    let db = manager.db_list.get(name).unwrap().value().clone();
+                    db.read().await.set("key", string("v"), 0).await.unwrap();
+                }
+            }
+            let connection = uuid::Uuid::new_v4();
+            crate::server::db_stat_set(connection, "mem-in-use".into()).await;
+            DB_STATE.lock().await.insert("mem-locked".into(), DataBaseState::LOCKED);
+
+            // 权重相同时占用越多的库越先卸载
+            let candidates = manager.unload_candidates().await;
+            let candidate = |name: &str| candidates.iter().find(|v| v.name == name).unwrap();
+            assert!(candidate("mem-a").score < candidate("mem-b").score);
+            assert_eq!(candidate("mem-in-use").blocked, Some("in use"));
+            assert_eq!(candidate("mem-locked").blocked, Some("locked"));
+            assert_eq!(candidate("mem-empty").blocked, Some("empty"));
+
+            // 需要的内存无法腾出时卸载所有可以卸载的库，然后返回错误
+            let state = Arc::downgrade(&db.read().await.state);
+            drop(db);
+            assert!(manager.check_eli_db(crate::memory::max()).await.is_err());
+
+            let mut loaded: Vec<String> = manager.db_list.iter().map(|v| v.key().clone()).collect();
+            loaded.sort();
+            assert_eq!(loaded, vec!["mem-empty", "mem-in-use", "mem-locked"]);
+
+            // 写入任务退出后索引占用随之归还
+            tokio::time::timeout(std::time::Duration::from_secs(1), async {
+                while state.upgrade().is_some() {
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .unwrap();
+
+            crate::server::db_stat_remove(connection).await;
+            DB_STATE.lock().await.remove("mem-locked");
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_drop_group() {
+        let root = temp_root("drop");
+        let storage = root.join("storage");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+
+            for name in ["system", "default"] {
+                let error = manager.drop_group(name).await.unwrap_err();
+                assert_eq!(error.to_string(), format!("group `{}` cannot be dropped", name));
+            }
+
+            for name in ["drop-busy", "drop-locked", "drop-loading", "drop-ok"] {
+                manager.select_to(name).await.unwrap();
+                let db = manager.db_list.get(name).unwrap().value().clone();
+                db.read().await.set("key", string("v"), 0).await.unwrap();
+            }
+
+            let connection = uuid::Uuid::new_v4();
+            crate::server::db_stat_set(connection, "drop-busy".into()).await;
+            DB_STATE.lock().await.insert("drop-locked".into(), DataBaseState::LOCKED);
+            manager.loading.insert("drop-loading".into(), Arc::new(LoadTask::new()));
+
+            for (name, reason) in [
+                ("drop-busy", "is in use"),
+                ("drop-locked", "is locked"),
+                ("drop-loading", "is loading"),
+            ] {
+                let error = manager.drop_group(name).await.unwrap_err();
+                assert_eq!(error.to_string(), format!("group `{}` {}", name, reason));
+
+                // 被拒绝时库仍然加载，数据保持不变
+                assert!(manager.db_list.contains_key(name));
+                assert!(storage.join(name).is_dir());
+            }
+
+            crate::server::db_stat_remove(connection).await;
+            DB_STATE.lock().await.remove("drop-locked");
+            manager.loading.remove("drop-loading");
+
+            let state = {
+                let db = manager.db_list.get("drop-ok").unwrap().value().clone();
+                let db = db.read().await;
+                assert!(db.memory() > 0);
+                Arc::downgrade(&db.state)
+            };
+
+            manager.drop_group("drop-ok").await.unwrap();
+            assert!(!manager.db_list.contains_key("drop-ok"));
+            assert!(!storage.join("drop-ok").exists());
+
+            // 写入任务退出后索引占用随之归还
+            tokio::time::timeout(std::time::Duration::from_secs(1), async {
+                while state.upgrade().is_some() {
+                    tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                }
+            })
+            .await
+            .unwrap();
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_fork_group() {
+        let root = temp_root("fork");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            let group = |name: &str| manager.db_list.get(name).unwrap().value().clone();
+
+            manager.select_to("fork-src").await.unwrap();
+            {
+                let db = group("fork-src");
+                let db = db.read().await;
+                db.set("a", string("1"), 0).await.unwrap();
+                db.set("b", string("1"), 0).await.unwrap();
+                db.rotate().await.unwrap();
+                db.set("c", string("1"), 0).await.unwrap();
+            }
+
+            manager.clone_group("fork-src", "fork-dst", true).await.unwrap();
+            manager.select_to("fork-dst").await.unwrap();
+
+            // 共享的归档文件不会被任何一方修改，之后的写入互不影响
+            {
+                let source = group("fork-src");
+                let source = source.read().await;
+                source.set("a", string("2"), 0).await.unwrap();
+                source.delete("b").await.unwrap();
+
+                let fork = group("fork-dst");
+                let fork = fork.read().await;
+                fork.set("a", string("3"), 0).await.unwrap();
+                fork.set("d", string("3"), 0).await.unwrap();
+            }
+
+            for reload in [false, true] {
+                if reload {
+                    for name in ["fork-src", "fork-dst"] {
+                        manager.unload_database(name.into()).await.unwrap();
+                        manager.select_to(name).await.unwrap();
+                    }
+                }
+
+                let source = group("fork-src");
+                let source = source.read().await;
+                assert_eq!(source.get("a").await.unwrap(), Some(string("2")));
+                assert_eq!(source.get("b").await.unwrap(), None);
+                assert_eq!(source.get("c").await.unwrap(), Some(string("1")));
+                assert_eq!(source.get("d").await.unwrap(), None);
+
+                let fork = group("fork-dst");
+                let fork = fork.read().await;
+                assert_eq!(fork.get("a").await.unwrap(), Some(string("3")));
+                assert_eq!(fork.get("b").await.unwrap(), Some(string("1")));
+                assert_eq!(fork.get("c").await.unwrap(), Some(string("1")));
+                assert_eq!(fork.get("d").await.unwrap(), Some(string("3")));
+            }
+
+            let error = manager.clone_group("system", "fork-system", true).await.unwrap_err();
+            assert_eq!(error.to_string(), "group `system` cannot be copied");
+            assert!(!root.join("storage").join("fork-system").exists());
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_copy_group_in_use() {
+        let root = temp_root("copy");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            manager.select_to("copy-src").await.unwrap();
+
+            let source = manager.db_list.get("copy-src").unwrap().value().clone();
+            source.read().await.set("a", string("1"), 0).await.unwrap();
+            source.read().await.rotate().await.unwrap();
+            source.read().await.set("b", string("1"), 0).await.unwrap();
+
+            // 其他连接正在使用源库时也可以复制
+            let connection = uuid::Uuid::new_v4();
+            crate::server::db_stat_set(connection, "copy-src".into()).await;
+
+            manager.clone_group("copy-src", "copy-dst", false).await.unwrap();
+            assert!(manager.db_list.contains_key("copy-src"));
+            source.read().await.set("c", string("1"), 0).await.unwrap();
+
+            crate::server::db_stat_remove(connection).await;
+
+            // 完整复制的归档文件与源库不共享
+            let archive = |name: &str| fs::read(root.join("storage").join(name).join("archive-1.db")).unwrap();
+            assert_eq!(archive("copy-src"), archive("copy-dst"));
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                let path = root.join("storage").join("copy-dst").join("archive-1.db");
+                assert_eq!(fs::metadata(path).unwrap().nlink(), 1);
+            }
+
+            manager.select_to("copy-dst").await.unwrap();
+            let copy = manager.db_list.get("copy-dst").unwrap().value().clone();
+            let copy = copy.read().await;
+            assert_eq!(copy.get("a").await.unwrap(), Some(string("1")));
+            assert_eq!(copy.get("b").await.unwrap(), Some(string("1")));
+            assert_eq!(copy.get("c").await.unwrap(), None);
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_rename_group() {
+        let root = temp_root("rename");
+        let storage = root.join("storage");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+
+            for name in ["rename-a", "rename-b"] {
+                manager.select_to(name).await.unwrap();
+                let db = manager.db_list.get(name).unwrap().value().clone();
+                db.read().await.set("key", string(name), 0).await.unwrap();
+            }
+
+            // 已加载或只存在于磁盘上的库名都不能作为新名称
+            for unload in [false, true] {
+                if unload {
+                    manager.unload_database("rename-b".into()).await.unwrap();
+                }
+
+                let error = manager.rename_group("rename-a", "rename-b").await.unwrap_err();
+                assert_eq!(error.to_string(), "group `rename-b` already exists");
+                assert!(manager.db_list.contains_key("rename-a"));
+                assert!(storage.join("rename-a").is_dir());
+            }
+
+            manager.rename_group("rename-a", "rename-c").await.unwrap();
+            assert!(!storage.join("rename-a").exists());
+
+            for (name, value) in [("rename-b", "rename-b"), ("rename-c", "rename-a")] {
+                manager.select_to(name).await.unwrap();
+                let db = manager.db_list.get(name).unwrap().value().clone();
+                assert_eq!(db.read().await.get("key").await.unwrap(), Some(string(value)));
+            }
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_restore_names() {
+        let root = temp_root("restore-names");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            manager.select_to("restore-src").await.unwrap();
+
+            let error = manager.restore_at("system", 0, "restore-x").await.unwrap_err();
+            assert_eq!(error.to_string(), "group `system` cannot be restored");
+
+            // 新库名不能跳出存储目录或者与临时目录冲突
+            for name in ["../restore-x", "~fork-restore-x", ""] {
+                let error = manager.restore_at("restore-src", 0, name).await.unwrap_err();
+                assert_eq!(error.to_string(), format!("invalid group name `{}`", name));
+
+                let error = manager.restore_group(&root.join("snapshot"), name).await.unwrap_err();
+                assert_eq!(error.to_string(), format!("invalid group name `{}`", name));
+            }
+            assert!(!root.join("restore-x").exists());
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_group_config() {
+        let root = temp_root("group-config");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            let connection = uuid::Uuid::new_v4();
+
+            // 通过命令执行，返回状态与内容
+            let command = |message: &str| {
+                let manager = manager.clone();
+                let message = message.to_string();
+                async move {
+                    let (state, content) = crate::command::CommandManager::command_handle(
+                        message,
+                        &mut true,
+                        &mut "config-a".to_string(),
+                        &mut "doson".to_string(),
+                        &manager.config,
+                        &manager,
+                        &connection,
+                    )
+                    .await;
+                    (state, String::from_utf8(content).unwrap())
+                }
+            };
+
+            manager.select_to("config-a").await.unwrap();
+            let db = manager.db_list.get("config-a").unwrap().value().clone();
+
+            // 没有指定过期时间的写入使用本库的默认过期时间
+            manager.set_group_config("config-a", "default_ttl", "600").await.unwrap();
+            assert_eq!(command("set a 1").await.0, NetPacketState::OK);
+            assert_eq!(command("set b 1 30").await.0, NetPacketState::OK);
+            assert_eq!(db.read().await.index_info("a").unwrap().time_stamp.1, 600);
+            assert_eq!(db.read().await.index_info("b").unwrap().time_stamp.1, 30);
+
+            // 只读模式拒绝所有写入，读取不受影响
+            manager.set_group_config("config-a", "mode", "read-only").await.unwrap();
+            {
+                let db = db.read().await;
+                let refused = "group `config-a` is read-only";
+                assert_eq!(db.set("c", string("1"), 0).await.unwrap_err().to_string(), refused);
+                assert_eq!(db.delete("a").await.unwrap_err().to_string(), refused);
+                assert_eq!(db.clean().await.unwrap_err().to_string(), refused);
+                assert_eq!(db.get("a").await.unwrap(), Some(DataValue::Number(1.0)));
+            }
+            assert_eq!(
+                command("set c 1").await,
+                (NetPacketState::ERR, "group `config-a` is read-only".to_string())
+            );
+            assert_eq!(command("get a").await.0, NetPacketState::OK);
+
+            // 配置保存在库目录中，重新加载后仍然生效
+            manager.unload_database("config-a".into()).await.unwrap();
+            drop(db);
+            assert!(root.join("storage").join("config-a").join(GROUP_CONFIG_FILE).is_file());
+
+            // 未加载的库直接修改配置文件
+            manager.set_group_config("config-a", "max_memory", "65536").await.unwrap();
+
+            manager.select_to("config-a").await.unwrap();
+            let db = manager.db_list.get("config-a").unwrap().value().clone();
+            let config = db.read().await.config().clone();
+            assert_eq!(config.default_ttl, 600);
+            assert_eq!(config.mode, GroupMode::ReadOnly);
+            assert_eq!(config.max_memory, 65536);
+            assert_eq!(manager.group_config("config-a").await.unwrap(), config);
+            assert!(db.read().await.set("c", string("1"), 0).await.is_err());
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_load_damaged_active() {
+        let root = temp_root("damaged");
+        let dir = root.join("group");
+        fs::create_dir_all(&dir).unwrap();
+
+        let record = |key: &str| {
+            let node = DataNode::new(key.into(), string(key), (1_700_000_000, 0));
+            record::encode_record(&node, Codec::default())
+        };
+
+        // 第二条记录的长度字段损坏，之后还有两条完整的记录
+        let mut damaged = record("b");
+        damaged[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut content = record::file_header();
+        content.extend_from_slice(&record("a"));
+        let position = content.len();
+        content.extend_from_slice(&damaged);
+        content.extend_from_slice(&record("c"));
+        content.extend_from_slice(&record("d"));
+        fs::write(dir.join("active.db"), &content).unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert_eq!(db.get("a").await.unwrap(), Some(string("a")));
+            assert_eq!(db.get("c").await.unwrap(), None);
+
+            // 截掉的部分完整保存在副本中
+            let sidecar: Vec<PathBuf> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|v| v.unwrap().path())
+                .filter(|v| v.to_string_lossy().contains("active.db.corrupt-"))
+                .collect();
+            assert_eq!(sidecar.len(), 1);
+            assert_eq!(fs::read(&sidecar[0]).unwrap(), content[position..]);
+            assert_eq!(fs::metadata(dir.join("active.db")).unwrap().len(), position as u64);
+
+            db.set("e", string("e"), 0).await.unwrap();
+        });
+
+        let sidecars = || {
+            fs::read_dir(&dir)
+                .unwrap()
+                .map(|v| v.unwrap().path())
+                .filter(|v| v.to_string_lossy().contains("active.db.corrupt-"))
+                .collect::<Vec<PathBuf>>()
+        };
+
+        // 长度字段没有超出上限但指向文件末尾之后，之后完整的记录同样保存在副本中
+        let mut content = fs::read(dir.join("active.db")).unwrap();
+        let size = content.len();
+        let mut damaged = record("f");
+        let overshoot = (damaged.len() + record("g").len()) as u32;
+        damaged[0..4].copy_from_slice(&overshoot.to_le_bytes());
+        content.extend_from_slice(&damaged);
+        content.extend_from_slice(&record("g"));
+        fs::write(dir.join("active.db"), &content).unwrap();
+        let before = sidecars();
+
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert_eq!(db.get("e").await.unwrap(), Some(string("e")));
+            assert_eq!(db.get("g").await.unwrap(), None);
+            assert_eq!(fs::metadata(dir.join("active.db")).unwrap().len(), size as u64);
+        });
+
+        let sidecar: Vec<PathBuf> = sidecars().into_iter().filter(|v| !before.contains(v)).collect();
+        assert_eq!(sidecar.len(), 1);
+        assert_eq!(fs::read(&sidecar[0]).unwrap(), content[size..]);
+
+        // 不足一个记录头的碎片直接截断，不保存副本
+        let mut content = fs::read(dir.join("active.db")).unwrap();
+        content.extend_from_slice(&record("h")[..6]);
+        fs::write(dir.join("active.db"), &content).unwrap();
+
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert_eq!(db.get("e").await.unwrap(), Some(string("e")));
+            assert_eq!(fs::metadata(dir.join("active.db")).unwrap().len(), size as u64);
+        });
+
+        assert_eq!(sidecars().len(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_load_damaged_archive() {
+        let root = temp_root("damaged-archive");
+        let dir = root.join("group");
+        fs::create_dir_all(&dir).unwrap();
+
+        let record = |key: &str| {
+            let node = DataNode::new(key.into(), string(key), (1_700_000_000, 0));
+            record::encode_record(&node, Codec::default())
+        };
+
+        // 归档文件中第二条记录的长度字段损坏，之后还有一条完整的记录
+        let mut damaged = record("b");
+        damaged[0..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let mut content = record::file_header();
+        content.extend_from_slice(&record("a"));
+        let position = content.len();
+        content.extend_from_slice(&damaged);
+        content.extend_from_slice(&record("c"));
+        fs::write(dir.join("archive-1.db"), &content).unwrap();
+        fs::write(dir.join("record.in"), b"2").unwrap();
+        fs::write(dir.join("active.db"), record::file_header()).unwrap();
+
+        // 与其他目录共享的归档文件不受影响
+        fs::hard_link(dir.join("archive-1.db"), root.join("linked.db")).unwrap();
+
+        let sidecars = || {
+            fs::read_dir(&dir)
+                .unwrap()
+                .map(|v| v.unwrap().path())
+                .filter(|v| v.to_string_lossy().contains("archive-1.db.corrupt-"))
+                .collect::<Vec<PathBuf>>()
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert_eq!(db.get("a").await.unwrap(), Some(string("a")));
+            assert_eq!(db.get("c").await.unwrap(), None);
+
+            // 截掉的部分保存在副本中，之后的合并不会丢失它
+            let sidecar = sidecars();
+            assert_eq!(sidecar.len(), 1);
+            assert_eq!(fs::read(&sidecar[0]).unwrap(), content[position..]);
+            assert_eq!(fs::metadata(dir.join("archive-1.db")).unwrap().len(), position as u64);
+            assert_eq!(fs::read(root.join("linked.db")).unwrap(), content);
+
+            let report = serde_json::to_value(db.verify().await.unwrap()).unwrap();
+            assert_eq!(report["problems"], json!([]));
+            assert_eq!(
+                report["saved"],
+                json!([sidecar[0].file_name().unwrap().to_string_lossy()])
+            );
+        });
+
+        // 重新加载时使用新的 hint 文件，不会再次截断或保存副本
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert_eq!(db.get("a").await.unwrap(), Some(string("a")));
+        });
+        assert_eq!(sidecars().len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_verify_during_write() {
+        let root = temp_root("verify-write");
+        let dir = root.join("group");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            db.set("a", string("a"), 0).await.unwrap();
+
+            // 写入任务正在追加的半条记录位于写入位置之后，不应被报告为不完整
+            let node = DataNode::new("b".into(), string("b"), (1_700_000_000, 0));
+            let half = &record::encode_record(&node, Codec::default())[..12];
+            let mut active = fs::OpenOptions::new().append(true).open(dir.join("active.db")).unwrap();
+            active.write_all(half).unwrap();
+
+            let report = serde_json::to_value(db.verify().await.unwrap()).unwrap();
+            assert_eq!(report["records"], 1);
+            assert_eq!(report["problems"], json!([]));
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_merge_with_writes() {
+        let root = temp_root("merge");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            db.set("a", string("1"), 0).await.unwrap();
+            db.set("b", string("2"), 0).await.unwrap();
+            db.set("c", string("3"), 0).await.unwrap();
+            db.set("a", string("10"), 0).await.unwrap();
+            db.delete("c").await.unwrap();
+            let db = Arc::new(RwLock::new(db));
+
+            let plan = db.write().await.prepare_merge().await.unwrap().unwrap();
+            let before = db.read().await.index_info("a").unwrap();
+
+            // 复制期间的写入进入新的活跃文件，合并结束时不能被旧位置覆盖
+            db.read().await.set("b", string("20"), 0).await.unwrap();
+            let output = tokio::task::spawn_blocking({
+                let plan = Arc::new(plan);
+                move || (plan.run(), plan)
+            })
+            .await
+            .unwrap();
+            let (output, plan) = (output.0.unwrap(), output.1);
+            db.read().await.set("d", string("4"), 0).await.unwrap();
+
+            db.write().await.finish_merge(&plan, output).await.unwrap();
+
+            let db = db.read().await;
+            let after = db.index_info("a").unwrap();
+            assert_eq!(after.file_id, 1);
+            assert_ne!(after.start_position, before.start_position);
+            assert_eq!(db.index_info("b").unwrap().file_id, db.state.file_id());
+            assert!(!root.join("group").join(MERGE_DIR).exists());
+
+            for (key, value) in [("a", "10"), ("b", "20"), ("d", "4")] {
+                assert_eq!(db.get(key).await.unwrap(), Some(string(value)));
+            }
+            assert_eq!(db.get("c").await.unwrap(), None);
+        });
+
+        // 重新加载后得到相同的数据
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            for (key, value) in [("a", "10"), ("b", "20"), ("d", "4")] {
+                assert_eq!(db.get(key).await.unwrap(), Some(string(value)));
+            }
+            assert_eq!(db.size(), 3);
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_merge_crash_recovery() {
+        let root = temp_root("merge-crash");
+        let dir = root.join("group");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut db = open(&root, "group", test_config("")).await;
+            for i in 0..16 {
+                db.set("a", string(&i.to_string()), 0).await.unwrap();
+            }
+            db.set("b", string("b"), 0).await.unwrap();
+
+            // 复制完成、完成标记已经写入，但替换归档文件之前崩溃
+            let plan = db.prepare_merge().await.unwrap().unwrap();
+            tokio::task::spawn_blocking(move || plan.run().map(|_| ()))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(dir.join(MERGE_DIR).join(MERGE_MARKER).is_file());
+        });
+
+        let size = fs::metadata(dir.join("archive-1.db")).unwrap().len();
+
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert!(!dir.join(MERGE_DIR).exists());
+            assert!(fs::metadata(dir.join("archive-1.db")).unwrap().len() < size);
+            assert_eq!(db.get("a").await.unwrap(), Some(string("15")));
+            assert_eq!(db.get("b").await.unwrap(), Some(string("b")));
+        });
+
+        // 没有完成标记的合并目录直接丢弃，原文件保持不变
+        fs::create_dir_all(dir.join(MERGE_DIR)).unwrap();
+        fs::write(dir.join(MERGE_DIR).join("archive-1.db"), b"partial").unwrap();
+        let size = fs::metadata(dir.join("archive-1.db")).unwrap().len();
+
+        runtime.block_on(async {
+            let db = open(&root, "group", test_config("")).await;
+            assert!(!dir.join(MERGE_DIR).exists());
+            assert_eq!(fs::metadata(dir.join("archive-1.db")).unwrap().len(), size);
+            assert_eq!(db.get("a").await.unwrap(), Some(string("15")));
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_replay_until() {
+        let root = temp_root("replay");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut db = open(&root, "group", test_config("")).await;
+
+            // (时间, key, 值)，值为 None 的是删除标记
+            let writes = [
+                (100, "a", Some("1")),
+                (100, "b", Some("1")),
+                (200, "a", Some("2")),
+                (200, "b", None),
+                (300, "a", Some("3")),
+                (300, "b", Some("3")),
+                (300, "c", Some("3")),
+            ];
+            for (time, key, value) in writes {
+                let (value, kind) = match value {
+                    Some(v) => (string(v), WriteKind::Set),
+                    None => (DataValue::None, WriteKind::Delete),
+                };
+                let node = DataNode::new(key.into(), value, (time, 0));
+                db.submit(node, kind).await.unwrap().await.unwrap();
+            }
+
+            let (staging, _) = db.freeze().await.unwrap();
+
+            // 每个时间点得到的 key 与当时的值
+            let cases: [(i64, &[(&str, &str)]); 4] = [
+                (50, &[]),
+                (150, &[("a", "1"), ("b", "1")]),
+                (250, &[("a", "2")]),
+                (300, &[("a", "3"), ("b", "3"), ("c", "3")]),
+            ];
+            for (timestamp, expected) in cases {
+                let name = format!("at-{}", timestamp);
+                let keys = replay_until(&staging, timestamp, &root.join(&name)).unwrap();
+                assert_eq!(keys, expected.len());
+
+                let replayed = open(&root, &name, test_config("")).await;
+                let mut found = replayed.keys().await;
+                found.sort();
+                assert_eq!(found, expected.iter().map(|v| v.0.to_string()).collect::<Vec<_>>());
+                for (key, value) in expected.iter() {
+                    assert_eq!(replayed.get(key).await.unwrap(), Some(string(value)));
+                }
+            }
+
+            fs::remove_dir_all(&staging).unwrap();
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_expire_sweep() {
+        let root = temp_root("sweep");
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let manager = manager(&root, "").await;
+            manager.select_to("ttl").await.unwrap();
+            let db = manager.db_list.get("ttl").unwrap().value().clone();
+
+            let memory = {
+                let db = db.read().await;
+                db.set("keep", string("v"), 0).await.unwrap();
+                let memory = db.memory();
+
+                // 写入时已经过期的 key，数量超过单批清理的上限
+                let now = chrono::Local::now().timestamp();
+                for i in 0..1000 {
+                    let node = DataNode::new(format!("k{}", i), string("v"), (now - 10, 5));
+                    db.submit(node, WriteKind::Set).await.unwrap().await.unwrap();
+                }
+                assert_eq!(db.size(), 1001);
+                assert!(db.memory() > memory);
+                memory
+            };
+
+            // 没有任何读取，清理任务直接删除并归还内存
+            crate::event::EventManager::init(manager.clone())
+                .await
+                ._c_expire_sweep(&mut 1)
+                .await;
+
+            let db = db.read().await;
+            assert_eq!(db.keys().await, vec!["keep".to_string()]);
+            assert_eq!(db.memory(), memory);
+            assert_eq!(db.next_expiry(), None);
+        });
+
+        // 删除标记已经写入，重新加载后不会再出现
+        runtime.block_on(async {
+            let db = open(&root.join("storage"), "ttl", test_config("")).await;
+            assert_eq!(db.size(), 1);
+        });
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
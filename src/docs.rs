@@ -31,13 +31,24 @@ pub const SUBCOMMAND_SERVICE_HELP: &str = "
 ";
 
 pub const SUBCOMMAND_DB_HELP: &str = "
-- preload <name> :                  preload a database to system.
+- preload <name> :                  preload a database to system in background, see progress in `db status`.
 - unload <name> :                   unload a database from system.
+- drop <name> :                     unload a database and delete its data from disk.
+- rename <old> <new> :              rename an unused database.
+- copy <src> <dst> :                copy a database with all of its data files.
+- fork <src> <dst> :                clone a database sharing its archive files [hard links].
+- config <name> [get [key]] :       print the per-database config.
+- config <name> set <key> <value> : change a per-database config item [mode, compression, ...].
 - list :                            get loaded database list.
 - lock <name> :                     lock a database [locked db cannot be unload].
 - unlock <name> :                   unlock a database [can be unload].
 - num :                             get loaded database number.
 - status :                          get all database status info.
+- verify <name> :                   check data files for corrupt or torn records.
+- merge <name> [status] :           compact a loaded database in background, or show its progress.
+- snapshot <name> <path> :          write a consistent, checksummed snapshot of a database.
+- restore <path> <name> :           restore a snapshot as a new database.
+- restore-at <name> <ts> <new> :    rebuild a database as it was at a unix timestamp into a new database.
 ";
 
 pub const SUBCOMMAND_INFO_HELP: &str = "
@@ -45,9 +56,12 @@ pub const SUBCOMMAND_INFO_HELP: &str = "
 - version :                         print doreadb system version.
 - max-connect-number | mcn :        doreadb max connection number.
 - server-startup-time | sst :       doreadb server startup time[timestamp].
-- total-index-number | tin :        doreadb maximum index number and current index number.
+- total-index-number | tin :        doreadb current loaded index number.
+- memory :                          index and cache memory usage and the max memory limit[bytes].
+- eviction :                        unload score of each database and why it can or cannot be unloaded.
 - connect-id | cid :                current connection id number[uuid].
 - keys :                            current database key list.
+- cache :                           current database value cache size and hit / miss count.
 - @{key_name} :
     - expire :                      data expire time[timestamp].
     - timestamp :                   data expire time and modify time[timestamp].
@@ -4,6 +4,12 @@ use tokio::time;
 
 use crate::database::DataBaseManager;
 
+// 过期清理每次持有写锁时最多删除的 key 数量
+const EXPIRE_SWEEP_BATCH: usize = 512;
+
+// 库权重衰减的间隔（秒）
+const WEIGHT_DECAY_INTERVAL: u32 = 60;
+
 #[derive(Debug)]
 pub struct EventManager {
     db_manager: Arc<DataBaseManager>,
@@ -20,62 +26,135 @@ impl EventManager {
 
         let mut tick_list: HashMap<String, u32> = HashMap::new();
 
-        tick_list.insert("_c_merge_db".into(), 60 * 60 * 48);
+        tick_list.insert("_c_merge_db".into(), 0);
         tick_list.insert("_c_save_all".into(), 2);
+        tick_list.insert("_c_expire_sweep".into(), 0);
+        tick_list.insert("_c_decay_weight".into(), 0);
 
         loop {
             self._c_merge_db(tick_list.get_mut("_c_merge_db").unwrap())
                 .await;
             self._c_save_all(tick_list.get_mut("_c_save_all").unwrap())
                 .await;
+            self._c_expire_sweep(tick_list.get_mut("_c_expire_sweep").unwrap())
+                .await;
+            self._c_decay_weight(tick_list.get_mut("_c_decay_weight").unwrap())
+                .await;
             interval.tick().await;
         }
     }
 
     pub async fn _c_merge_db(&self, tick: &mut u32) {
-        if *tick != 60 * 60 * 48 {
+        if *tick != 60 {
             *tick += 1;
             return;
         }
 
-        // 收集数据库名列表，然后逐库加锁操作
+        let config = &self.db_manager.config.database;
+
+        // 收集数据库名列表，然后逐库检查碎片率
         let db_names: Vec<String> = self.db_manager.db_list.iter().map(|e| e.key().clone()).collect();
 
         for name in db_names {
             let db_arc = match self.db_manager.db_list.get(&name) {
-                Some(arc) => arc,
+                Some(arc) => arc.value().clone(),
                 None => continue,
             };
 
-            // 前置检查：归档文件 ≤3 个则跳过 merge
-            {
+            let fragmentation = {
                 let db = db_arc.read().await;
-                if db.record_count() <= 3 {
+                if db.merge_progress().is_running() {
                     continue;
                 }
+                match db.fragmentation().await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::error!("fragmentation check error for {}: {}", name, e);
+                        continue;
+                    }
+                }
+            };
+
+            if fragmentation.dead_ratio() < config.merge_dead_ratio
+                || fragmentation.dead_bytes() < config.merge_min_dead_size
+            {
+                continue;
             }
 
-            // try_write：若锁被占用则短暂等待重试，不阻塞其他库
+            log::info!(
+                "@{} dead bytes {} ({:.1}%), merge started.",
+                name,
+                fragmentation.dead_bytes(),
+                fragmentation.dead_ratio() * 100.0,
+            );
+
+            // 合并在后台任务中执行，不阻塞事件循环
+            if let Err(e) = self.db_manager.merge_group(&name).await {
+                log::error!("merge operation error for {}: {}", name, e);
+            }
+        }
+
+        *tick = 0;
+    }
+
+    /// 主动清理过期的 key（每 2 秒执行，每批最多处理 EXPIRE_SWEEP_BATCH 个后释放锁）
+    pub async fn _c_expire_sweep(&self, tick: &mut u32) {
+        if *tick != 1 {
+            *tick += 1;
+            return;
+        }
+
+        let now = chrono::Local::now().timestamp();
+
+        let db_names: Vec<String> = self.db_manager.db_list.iter().map(|e| e.key().clone()).collect();
+
+        for name in db_names {
+            let db_arc = match self.db_manager.db_list.get(&name) {
+                Some(arc) => arc.value().clone(),
+                None => continue,
+            };
+
+            let mut total = 0;
+
             loop {
-                match db_arc.try_write() {
-                    Ok(mut db) => {
-                        match db.merge().await {
-                            Ok(_) => {}
-                            Err(e) => log::error!("merge operation error for {}: {}", name, e),
-                        }
+                // 先用读锁判断，没有到期的 key 时不占用写锁
+                match db_arc.read().await.next_expiry() {
+                    Some(deadline) if deadline < now => {}
+                    _ => break,
+                }
+
+                match db_arc.write().await.sweep_expired(EXPIRE_SWEEP_BATCH).await {
+                    Ok(0) => break,
+                    Ok(v) => total += v,
+                    Err(e) => {
+                        log::error!("expire sweep error for {}: {}", name, e);
                         break;
                     }
-                    Err(_) => {
-                        // 锁被占用，等待 100ms 后重试
-                        time::sleep(time::Duration::from_millis(100)).await;
-                    }
                 }
             }
+
+            if total > 0 {
+                log::debug!("@{} {} expired keys removed.", name, total);
+            }
         }
 
         *tick = 0;
     }
 
+    /// 衰减库的权重，很久之前频繁访问的库也可以被卸载
+    pub async fn _c_decay_weight(&self, tick: &mut u32) {
+        if *tick != WEIGHT_DECAY_INTERVAL {
+            *tick += 1;
+            return;
+        }
+
+        self.db_manager
+            .decay_weights(WEIGHT_DECAY_INTERVAL as u64)
+            .await;
+
+        *tick = 0;
+    }
+
     pub async fn _c_save_all(&self, tick: &mut u32) {
         if *tick != 60 * 5 {
             *tick += 1;
@@ -1,12 +1,12 @@
+use std::cell::RefCell;
 use std::sync::Arc;
 
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
-use tokio::sync::RwLock;
 
 use crate::command::CommandManager;
 use crate::configure::DoreaFileConfig;
-use crate::database::{DataBase, DataBaseManager};
+use crate::database::{DataBaseManager, DEFERRED_COMMIT};
 use crate::network::{Frame, NetPacket, NetPacketState, MAGIC, PROTOCOL_VERSION};
 use crate::Result;
 
@@ -118,7 +118,7 @@ pub(crate) async fn process(
             }
 
             // 将预设的数据转换为数据本值
-            let body = process_response_body(&res.1, startup_time, &database_manager, &config).await;
+            let body = process_response_body(&res.1, startup_time, &database_manager).await;
 
             NetPacket::make(body, res.0).send(socket).await?;
         }
@@ -138,24 +138,42 @@ async fn process_batch(
 ) -> Vec<(Vec<u8>, NetPacketState)> {
     let mut responses = Vec::with_capacity(requests.len());
 
-    for message in requests {
-        let res = CommandManager::command_handle(
-            String::from_utf8_lossy(&message[..]).to_string(),
-            auth,
-            current,
-            value_ser_style,
-            config,
-            database_manager,
-            connect_id,
-        )
+    // 批内写入只登记持久化凭证，整批执行完成后统一等待（共享 fsync）
+    let tickets = DEFERRED_COMMIT
+        .scope(RefCell::new(vec![]), async {
+            for message in requests {
+                let res = CommandManager::command_handle(
+                    String::from_utf8_lossy(&message[..]).to_string(),
+                    auth,
+                    current,
+                    value_ser_style,
+                    config,
+                    database_manager,
+                    connect_id,
+                )
+                .await;
+
+                if res.0 == NetPacketState::EMPTY {
+                    continue;
+                }
+
+                let body = process_response_body(&res.1, startup_time, database_manager).await;
+                responses.push((body, res.0));
+            }
+
+            DEFERRED_COMMIT.with(|v| v.take())
+        })
         .await;
 
-        if res.0 == NetPacketState::EMPTY {
-            continue;
+    for ticket in tickets {
+        if let Err(e) = ticket.wait().await {
+            // 无法确认批内写入已落盘，整批返回错误
+            let msg = format!("durability sync failed: {}", e);
+            return responses
+                .into_iter()
+                .map(|_| (msg.as_bytes().to_vec(), NetPacketState::ERR))
+                .collect();
         }
-
-        let body = process_response_body(&res.1, startup_time, database_manager, config).await;
-        responses.push((body, res.0));
     }
 
     responses
@@ -166,41 +184,14 @@ async fn process_response_body(
     res_body: &[u8],
     startup_time: i64,
     database_manager: &Arc<DataBaseManager>,
-    config: &DoreaFileConfig,
 ) -> Vec<u8> {
     match String::from_utf8_lossy(res_body).to_string().as_str() {
         "@[SERVER_STARTUP_TIME]" => startup_time.to_string().as_bytes().to_vec(),
         val if val.starts_with("@[PRELOAD_DB]:") => {
-            let db_name = val[14..].to_string();
-            let tmp_db_manager = database_manager.clone();
-            let db_config = config.database.clone();
-            tokio::spawn(async move {
-                crate::database::DB_STATE
-                    .lock()
-                    .await
-                    .insert(db_name.clone(), crate::database::DataBaseState::LOADING);
-
-                let storage_path = tmp_db_manager.location.clone().join("storage");
-
-                let ndb = DataBase::init(
-                    db_name.to_string(),
-                    storage_path,
-                    db_config,
-                )
-                .await;
-
-                match tmp_db_manager.load_from(&db_name, Arc::new(RwLock::new(ndb))).await {
-                    Ok(_) => {
-                        crate::database::DB_STATE.lock().await.insert(
-                            db_name.clone(),
-                            crate::database::DataBaseState::NORMAL,
-                        );
-                    }
-                    Err(e) => {
-                        log::error!("database load error: {}", e.to_string())
-                    }
-                };
-            });
+            // 在后台加载，进度可以通过 `db status` 查看
+            if let Err(e) = database_manager.start_loading(&val[14..]) {
+                log::error!("database load error: {}", e);
+            }
             vec![]
         }
         _ => res_body.to_vec(),
@@ -0,0 +1,224 @@
+//! Hint 文件（类似 Bitcask 的 hint file）
+//!
+//! 每个 `archive-N.db` 在归档或合并时都会写出一份 `archive-N.hint`，
+//! 其中只记录该文件内每个 key 的最后一条记录位置（含删除标记）。
+//! 加载索引时优先读取 hint 文件，无需再扫描并解析整个数据文件。
+//!
+//! 文件结构（小端序）：
+//!
+//! ```text
+//! | MAGIC "DHNT" | VERSION u8 | FILE_ID u32 | DATA_SIZE u64 | COUNT u32 |
+//! | KEY_LEN u32 | KEY | START u64 | END u64 | WRITE_TIME i64 | EXPIRE u64 | TOMBSTONE u8 | ...
+//! | CRC32 u32 |
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+use crate::database::CASTAGNOLI;
+
+const HINT_MAGIC: &[u8; 4] = b"DHNT";
+const HINT_VERSION: u8 = 1;
+const HINT_HEADER_SIZE: usize = 4 + 1 + 4 + 8 + 4;
+
+/// hint 文件中的单条索引记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HintEntry {
+    pub(crate) key: String,
+    pub(crate) start_position: u64,
+    pub(crate) end_position: u64,
+    pub(crate) time_stamp: (i64, u64),
+    pub(crate) tombstone: bool,
+}
+
+pub(crate) fn hint_path(root: &Path, file_id: u32) -> PathBuf {
+    root.join(format!("archive-{}.hint", file_id))
+}
+
+/// 将 hint 信息编码为二进制
+pub(crate) fn encode(file_id: u32, data_size: u64, entries: &[HintEntry]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HINT_HEADER_SIZE + entries.len() * 48);
+
+    buf.extend_from_slice(HINT_MAGIC);
+    buf.push(HINT_VERSION);
+    buf.extend_from_slice(&file_id.to_le_bytes());
+    buf.extend_from_slice(&data_size.to_le_bytes());
+    buf.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+    for entry in entries {
+        buf.extend_from_slice(&(entry.key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(entry.key.as_bytes());
+        buf.extend_from_slice(&entry.start_position.to_le_bytes());
+        buf.extend_from_slice(&entry.end_position.to_le_bytes());
+        buf.extend_from_slice(&entry.time_stamp.0.to_le_bytes());
+        buf.extend_from_slice(&entry.time_stamp.1.to_le_bytes());
+        buf.push(entry.tombstone as u8);
+    }
+
+    let crc = CASTAGNOLI.checksum(&buf);
+    buf.extend_from_slice(&crc.to_le_bytes());
+
+    buf
+}
+
+/// 解析 hint 文件内容，`file_id` 与 `data_size` 必须与实际数据文件一致
+pub(crate) fn decode(buf: &[u8], file_id: u32, data_size: u64) -> crate::Result<Vec<HintEntry>> {
+    if buf.len() < HINT_HEADER_SIZE + 4 {
+        return Err(anyhow!("hint file too short"));
+    }
+
+    let (body, crc) = buf.split_at(buf.len() - 4);
+    if CASTAGNOLI.checksum(body) != u32::from_le_bytes(crc.try_into()?) {
+        return Err(anyhow!("hint file checksum mismatch"));
+    }
+
+    let mut reader = Reader { buf: body, pos: 0 };
+
+    if reader.take(4)? != HINT_MAGIC {
+        return Err(anyhow!("hint file magic mismatch"));
+    }
+    if reader.u8()? != HINT_VERSION {
+        return Err(anyhow!("hint file version unsupported"));
+    }
+    if reader.u32()? != file_id {
+        return Err(anyhow!("hint file id mismatch"));
+    }
+    if reader.u64()? != data_size {
+        return Err(anyhow!("hint file is stale"));
+    }
+
+    let count = reader.u32()? as usize;
+    let mut entries = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let key_len = reader.u32()? as usize;
+        let key = String::from_utf8(reader.take(key_len)?.to_vec())?;
+        entries.push(HintEntry {
+            key,
+            start_position: reader.u64()?,
+            end_position: reader.u64()?,
+            time_stamp: (reader.u64()? as i64, reader.u64()?),
+            tombstone: reader.u8()? != 0,
+        });
+    }
+
+    if reader.pos != body.len() {
+        return Err(anyhow!("hint file has trailing data"));
+    }
+
+    Ok(entries)
+}
+
+/// 写出 hint 文件（先写临时文件再重命名，避免留下半个 hint）
+///
+/// hint 文件中的 key 是明文，开启加密后不再写出，加载时改为扫描数据文件。
+pub(crate) fn write(
+    root: &Path,
+    file_id: u32,
+    data_size: u64,
+    entries: &[HintEntry],
+) -> crate::Result<()> {
+    let target = hint_path(root, file_id);
+
+    if crate::crypto::keyring().current().is_some() {
+        let _ = fs::remove_file(&target);
+        return Ok(());
+    }
+    let temp = root.join(format!("archive-{}.hint.tmp", file_id));
+
+    fs::write(&temp, encode(file_id, data_size, entries))?;
+    fs::rename(&temp, &target)?;
+
+    Ok(())
+}
+
+/// 读取 hint 文件，文件不存在或校验失败时返回 `None`
+pub(crate) fn read(root: &Path, file_id: u32, data_size: u64) -> Option<Vec<HintEntry>> {
+    let path = hint_path(root, file_id);
+
+    let buf = fs::read(&path).ok()?;
+
+    match decode(&buf, file_id, data_size) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::warn!("hint file {:?} ignored: {}.", path, e);
+            None
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> crate::Result<&'a [u8]> {
+        if self.pos + len > self.buf.len() {
+            return Err(anyhow!("hint file truncated"));
+        }
+        let v = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> crate::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> crate::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> crate::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<HintEntry> {
+        vec![
+            HintEntry {
+                key: "foo".into(),
+                start_position: 34,
+                end_position: 120,
+                time_stamp: (1_700_000_000, 0),
+                tombstone: false,
+            },
+            HintEntry {
+                key: "中文".into(),
+                start_position: 122,
+                end_position: 180,
+                time_stamp: (1_700_000_001, 60),
+                tombstone: true,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_hint_roundtrip() {
+        let buf = encode(3, 4096, &entries());
+        assert_eq!(decode(&buf, 3, 4096).unwrap(), entries());
+    }
+
+    #[test]
+    fn test_hint_rejects_invalid() {
+        let buf = encode(3, 4096, &entries());
+
+        // 数据文件大小变化、文件编号不一致都视为失效
+        assert!(decode(&buf, 3, 4097).is_err());
+        assert!(decode(&buf, 4, 4096).is_err());
+
+        // 任意字节损坏都会被 CRC 检出
+        let mut broken = buf.clone();
+        broken[HINT_HEADER_SIZE + 2] ^= 0xFF;
+        assert!(decode(&broken, 3, 4096).is_err());
+
+        assert!(decode(&buf[..buf.len() - 1], 3, 4096).is_err());
+    }
+}
@@ -6,7 +6,10 @@ use once_cell::sync::Lazy;
 // Dorea db version (current)
 pub const DOREA_VERSION: &str = "0.4.0";
 
-// current version support load-storage version list.
+// storage format version written into data file headers (2: records may be compressed or encrypted).
+pub(crate) const STORAGE_FORMAT_VERSION: u16 = 2;
+
+// legacy (json line) storage versions which can be migrated to the current format.
 #[allow(dead_code)]
 static COMPATIBLE_VERSION: Lazy<Vec<String>> = Lazy::new(|| {
     vec![
@@ -43,12 +46,18 @@ pub mod macros;
 #[cfg(feature = "processor")]
 pub mod docs;
 
+#[cfg(feature = "server")]
+mod cache;
+
 #[cfg(feature = "server")]
 mod command;
 
 #[cfg(feature = "server")]
 mod configure;
 
+#[cfg(feature = "server")]
+mod crypto;
+
 #[cfg(feature = "server")]
 mod database;
 
@@ -58,11 +67,29 @@ mod event;
 #[cfg(feature = "server")]
 mod handle;
 
+#[cfg(feature = "server")]
+mod hint;
+
 #[cfg(feature = "server")]
 mod logger;
 
+#[cfg(feature = "server")]
+mod memory;
+
+#[cfg(feature = "server")]
+mod reader;
+
+#[cfg(feature = "server")]
+mod record;
+
 #[cfg(feature = "server")]
 mod service;
+
+#[cfg(feature = "server")]
+mod snapshot;
+
+#[cfg(feature = "server")]
+mod storage;
 mod tool;
 
 type Result<T> = std::result::Result<T, anyhow::Error>;
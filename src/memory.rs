@@ -0,0 +1,62 @@
+//! 内存占用统计
+//!
+//! 索引（包括保留的历史版本）与数据缓存的占用按字节估算，
+//! 加载库、写入新 key 以及缓存数据时都以 `max_memory` 为上限。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde_json::json;
+
+// 所有已加载库的索引占用（字节）
+static INDEX_MEMORY: AtomicU64 = AtomicU64::new(0);
+
+// 所有库的缓存占用（字节）
+static CACHE_MEMORY: AtomicU64 = AtomicU64::new(0);
+
+static MAX_MEMORY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+pub(crate) fn set_max(bytes: u64) {
+    MAX_MEMORY.store(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn max() -> u64 {
+    MAX_MEMORY.load(Ordering::Relaxed)
+}
+
+pub(crate) fn index() -> u64 {
+    INDEX_MEMORY.load(Ordering::Relaxed)
+}
+
+pub(crate) fn cache() -> u64 {
+    CACHE_MEMORY.load(Ordering::Relaxed)
+}
+
+/// 索引与缓存的总占用
+pub(crate) fn used() -> u64 {
+    index() + cache()
+}
+
+pub(crate) fn charge_index(bytes: i64) {
+    charge(&INDEX_MEMORY, bytes);
+}
+
+pub(crate) fn charge_cache(bytes: i64) {
+    charge(&CACHE_MEMORY, bytes);
+}
+
+fn charge(counter: &AtomicU64, bytes: i64) {
+    if bytes >= 0 {
+        counter.fetch_add(bytes as u64, Ordering::Relaxed);
+    } else {
+        counter.fetch_sub(bytes.unsigned_abs(), Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn report() -> serde_json::Value {
+    json!({
+        "used": used(),
+        "max": max(),
+        "index": index(),
+        "cache": cache(),
+    })
+}
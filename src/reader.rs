@@ -0,0 +1,251 @@
+//! 数据文件读取句柄池
+//!
+//! 每个库缓存已经打开的数据文件句柄，读取时使用按位置读取（pread），
+//! 多个读请求可以共享同一个句柄而不需要 seek，也不会在每次 GET 时重新打开文件。
+//!
+//! 归档文件写入后不会再被修改，开启 `mmap_archives` 后改为内存映射读取（仅 unix）。
+//! 活跃文件在归档时只是被重命名，已经打开的句柄在归档之后仍然指向同一个文件。
+//! 合并替换归档文件时旧文件只是被重命名覆盖，已经映射或打开的旧文件在句柄释放前仍然有效，
+//! 但替换后需要调用 [`ReadPool::clear`] 让后续读取使用新文件。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 每个库最多缓存的文件句柄数量
+const MAX_READ_HANDLES: usize = 64;
+
+#[derive(Debug)]
+enum ReadHandle {
+    File(fs::File),
+    #[cfg(unix)]
+    Mmap(Mmap),
+}
+
+impl ReadHandle {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        match self {
+            ReadHandle::File(file) => read_exact_at(file, buf, offset),
+            #[cfg(unix)]
+            ReadHandle::Mmap(map) => {
+                let data = map.as_slice();
+                let start = offset as usize;
+                match start.checked_add(buf.len()) {
+                    Some(end) if offset <= usize::MAX as u64 && end <= data.len() => {
+                        buf.copy_from_slice(&data[start..end]);
+                        Ok(())
+                    }
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    )),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &fs::File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+/// 只读的内存映射
+#[cfg(unix)]
+#[derive(Debug)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// 映射区域只读，且在 Drop 之前一直有效
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+    fn map(file: &fs::File) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "empty file"));
+        }
+
+        // SAFETY: 只读映射，归档文件之后不会被原地修改（合并时整体替换为新文件）
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: ptr 指向长度为 len 的有效映射
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        // SAFETY: ptr 与 len 来自 mmap 的返回值
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// 某个库的数据文件读取句柄（按文件编号缓存）
+#[derive(Debug, Default)]
+pub(crate) struct ReadPool {
+    mmap: bool,
+    handles: Mutex<HashMap<u32, Arc<ReadHandle>>>,
+}
+
+impl ReadPool {
+    pub(crate) fn new(mmap: bool) -> Self {
+        Self {
+            mmap: mmap && cfg!(unix),
+            handles: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 从编号为 `file_id` 的数据文件的 `offset` 处读取 `len` 字节
+    ///
+    /// `active` 为当前活跃文件编号，只有归档文件会使用内存映射。
+    pub(crate) fn read(
+        &self,
+        root: &Path,
+        file_id: u32,
+        active: &AtomicU32,
+        offset: u64,
+        len: usize,
+    ) -> io::Result<Vec<u8>> {
+        let handle = self.handle(root, file_id, active)?;
+
+        let mut buf = vec![0_u8; len];
+        handle.read_exact_at(&mut buf, offset)?;
+
+        Ok(buf)
+    }
+
+    fn handle(&self, root: &Path, file_id: u32, active: &AtomicU32) -> io::Result<Arc<ReadHandle>> {
+        if let Some(v) = self.handles.lock().unwrap().get(&file_id) {
+            return Ok(v.clone());
+        }
+
+        // 活跃文件可能在打开的同时被归档：归档时先重命名再更新编号，
+        // 打开之后编号仍然没有变化，说明打开的一定是该编号对应的文件
+        if active.load(Ordering::Acquire) == file_id {
+            if let Ok(file) = fs::File::open(root.join("active.db")) {
+                if active.load(Ordering::Acquire) == file_id {
+                    return Ok(self.insert(file_id, ReadHandle::File(file)));
+                }
+            }
+        }
+
+        let path = root.join(format!("archive-{}.db", file_id));
+        let file = fs::File::open(&path)?;
+
+        let handle = match self.mmap {
+            #[cfg(unix)]
+            true => match Mmap::map(&file) {
+                Ok(v) => ReadHandle::Mmap(v),
+                Err(e) => {
+                    log::warn!("mmap {:?} failed, fallback to pread: {}.", path, e);
+                    ReadHandle::File(file)
+                }
+            },
+            _ => ReadHandle::File(file),
+        };
+
+        Ok(self.insert(file_id, handle))
+    }
+
+    fn insert(&self, file_id: u32, handle: ReadHandle) -> Arc<ReadHandle> {
+        let handle = Arc::new(handle);
+
+        let mut handles = self.handles.lock().unwrap();
+        if handles.len() >= MAX_READ_HANDLES {
+            handles.clear();
+        }
+        handles.insert(file_id, handle.clone());
+
+        handle
+    }
+
+    /// 丢弃某个文件的句柄（活跃文件归档后改用内存映射）
+    pub(crate) fn invalidate(&self, file_id: u32) {
+        self.handles.lock().unwrap().remove(&file_id);
+    }
+
+    /// 丢弃所有句柄（数据文件被整体替换时调用）
+    pub(crate) fn clear(&self) {
+        self.handles.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_pool() {
+        let dir = std::env::temp_dir().join(format!("dorea-reader-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("archive-1.db"), b"hello dorea").unwrap();
+        fs::write(dir.join("active.db"), b"hello world").unwrap();
+
+        let active = AtomicU32::new(2);
+
+        for mmap in [false, true] {
+            let pool = ReadPool::new(mmap);
+            assert_eq!(pool.read(&dir, 1, &active, 6, 5).unwrap(), b"dorea");
+            assert_eq!(pool.read(&dir, 2, &active, 6, 5).unwrap(), b"world");
+            assert!(pool.read(&dir, 1, &active, 8, 5).is_err());
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,841 @@
+//! 数据文件存储格式
+//!
+//! 文件头：
+//!
+//! ```text
+//! | MAGIC "DRDB" | VERSION u16 | HEADER_LEN u16 | FLAGS u32 | [KEY_CHECK [u8; 16]] | HEADER_CRC u32 |
+//! ```
+//!
+//! 文件头 `FLAGS` 的第 0 位表示文件中的记录已加密，此时文件头中带有加密密钥的校验值（见 `crypto.rs`）。
+//!
+//! 记录（小端序）：
+//!
+//! ```text
+//! | PAYLOAD_LEN u32 | FLAGS u8 | CRC u32 | PAYLOAD |
+//! ```
+//!
+//! `CRC` 覆盖 `PAYLOAD_LEN`、`FLAGS` 与 `PAYLOAD`，`PAYLOAD` 为 `DataNode` 的二进制编码。
+//! `FLAGS` 的低 2 位为压缩算法（0 不压缩、1 zstd、2 lz4），压缩后的 `PAYLOAD` 为编码结果压缩后的数据，
+//! 同一个文件中可以混合存放压缩与未压缩的记录。
+//! `FLAGS` 的第 2 位表示 `PAYLOAD` 已加密（先压缩再加密），加密数据中带有密钥编号，
+//! 因此按位置读取单条记录时不需要文件头。
+//!
+//! 旧版本（`COMPATIBLE_VERSION` 中的 md5 文件头 + CRLF 分隔的 JSON）只用于迁移时读取。
+
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::anyhow;
+use doson::binary::Binary;
+
+use crate::configure::Compression;
+use crate::crypto::{self, Key, Keyring, KEY_CHECK_SIZE};
+use crate::database::{DataNode, CASTAGNOLI};
+use crate::value::DataValue;
+
+pub(crate) const FILE_MAGIC: &[u8; 4] = b"DRDB";
+pub(crate) const FILE_HEADER_SIZE: u64 = 16;
+pub(crate) const RECORD_HEADER_SIZE: u64 = 9;
+
+// 旧版本文件头：32 字节 md5 + CRLF
+const LEGACY_HEADER_SIZE: u64 = 34;
+
+// 单条记录长度上限，超出则视为长度字段已损坏
+const MAX_RECORD_SIZE: u64 = 1024 * 1024 * 256;
+
+// 文件头 FLAGS：记录已加密
+const FILE_FLAG_ENCRYPTED: u32 = 1;
+
+// 记录头 FLAGS 中的压缩算法
+const FLAG_COMPRESSION: u8 = 0b11;
+const FLAG_ZSTD: u8 = 1;
+const FLAG_LZ4: u8 = 2;
+
+// 记录头 FLAGS：数据已加密
+const FLAG_ENCRYPTED: u8 = 0b100;
+
+const ZSTD_LEVEL: i32 = 3;
+
+// 未配置压缩阈值时，编码后超过该字节数的记录才压缩
+const DEFAULT_COMPRESS_THRESHOLD: u64 = 1024;
+
+/// 写入记录时使用的压缩设置
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Codec {
+    compression: Compression,
+    threshold: u64,
+}
+
+impl Codec {
+    /// `threshold` 为 0 时使用默认阈值
+    pub(crate) fn new(compression: Compression, threshold: u64) -> Self {
+        Self {
+            compression,
+            threshold: match threshold {
+                0 => DEFAULT_COMPRESS_THRESHOLD,
+                v => v,
+            },
+        }
+    }
+
+    /// 压缩编码后的数据，不需要压缩或压缩后没有变小时返回 None
+    fn compress(&self, payload: &[u8]) -> Option<(u8, Vec<u8>)> {
+        if (payload.len() as u64) < self.threshold {
+            return None;
+        }
+
+        let (flag, compressed) = match self.compression {
+            Compression::None => return None,
+            Compression::Zstd => (FLAG_ZSTD, zstd::bulk::compress(payload, ZSTD_LEVEL).ok()?),
+            Compression::Lz4 => (FLAG_LZ4, lz4_flex::compress_prepend_size(payload)),
+        };
+
+        (compressed.len() < payload.len()).then_some((flag, compressed))
+    }
+}
+
+fn decompress(flag: u8, payload: &[u8]) -> crate::Result<Vec<u8>> {
+    let data = match flag {
+        FLAG_ZSTD => zstd::stream::decode_all(payload)?,
+        FLAG_LZ4 => {
+            if payload.len() < 4 {
+                return Err(anyhow!("record payload truncated"));
+            }
+            let size = u32::from_le_bytes(payload[0..4].try_into()?) as u64;
+            if size > MAX_RECORD_SIZE {
+                return Err(anyhow!("record too large after decompression"));
+            }
+            lz4_flex::decompress(&payload[4..], size as usize)?
+        }
+        flag => return Err(anyhow!("unknown record compression {}", flag)),
+    };
+
+    if data.len() as u64 > MAX_RECORD_SIZE {
+        return Err(anyhow!("record too large after decompression"));
+    }
+
+    Ok(data)
+}
+
+/// 记录不压缩时的长度（含记录头）
+pub(crate) fn logical_size(node: &DataNode) -> u64 {
+    let mut payload = Vec::with_capacity(64);
+    encode_node(node, &mut payload);
+    RECORD_HEADER_SIZE + payload.len() as u64
+}
+
+/// 数据文件的格式
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum FileFormat {
+    /// 当前版本的二进制格式，携带文件头长度以及加密密钥的编号（未加密时为 None）
+    Binary { header_len: u64, key_id: Option<u32> },
+    /// 旧版本 JSON 格式（需要迁移）
+    Legacy,
+}
+
+/// 新数据文件的文件头长度
+pub(crate) fn header_size() -> u64 {
+    match crypto::keyring().current() {
+        Some(_) => FILE_HEADER_SIZE + KEY_CHECK_SIZE as u64,
+        None => FILE_HEADER_SIZE,
+    }
+}
+
+/// 生成新数据文件的文件头（配置了密钥时带有当前密钥的校验值）
+pub(crate) fn file_header() -> Vec<u8> {
+    file_header_with(crypto::keyring().current())
+}
+
+fn file_header_with(key: Option<&Key>) -> Vec<u8> {
+    let (flags, header_len) = match key {
+        Some(_) => (FILE_FLAG_ENCRYPTED, FILE_HEADER_SIZE + KEY_CHECK_SIZE as u64),
+        None => (0, FILE_HEADER_SIZE),
+    };
+
+    let mut buf = Vec::with_capacity(header_len as usize);
+
+    buf.extend_from_slice(FILE_MAGIC);
+    buf.extend_from_slice(&crate::STORAGE_FORMAT_VERSION.to_le_bytes());
+    buf.extend_from_slice(&(header_len as u16).to_le_bytes());
+    buf.extend_from_slice(&flags.to_le_bytes());
+
+    if let Some(key) = key {
+        buf.extend_from_slice(key.check());
+    }
+
+    let crc = CASTAGNOLI.checksum(&buf);
+    buf.extend_from_slice(&crc.to_le_bytes());
+
+    buf
+}
+
+/// 读取并检查数据文件头，加密文件的密钥没有配置时返回错误
+pub(crate) fn detect_format(path: &Path) -> crate::Result<FileFormat> {
+    detect_format_with(path, &crypto::keyring())
+}
+
+fn detect_format_with(path: &Path, keyring: &Keyring) -> crate::Result<FileFormat> {
+    let mut file = fs::File::open(path)?;
+
+    let mut magic = [0_u8; 4];
+    file.read_exact(&mut magic)?;
+
+    if &magic == FILE_MAGIC {
+        let mut rest = [0_u8; 4];
+        file.read_exact(&mut rest)?;
+
+        let version = u16::from_le_bytes([rest[0], rest[1]]);
+        let header_len = u16::from_le_bytes([rest[2], rest[3]]) as u64;
+
+        if version > crate::STORAGE_FORMAT_VERSION {
+            return Err(anyhow!(
+                "storage format version {} unsupported (max {})",
+                version,
+                crate::STORAGE_FORMAT_VERSION
+            ));
+        }
+
+        if header_len < FILE_HEADER_SIZE {
+            return Err(anyhow!("storage header corrupted"));
+        }
+
+        let mut header = vec![0_u8; header_len as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+
+        let (body, crc) = header.split_at(header.len() - 4);
+        if CASTAGNOLI.checksum(body) != u32::from_le_bytes(crc.try_into()?) {
+            return Err(anyhow!("storage header corrupted"));
+        }
+
+        let flags = u32::from_le_bytes(body[8..12].try_into()?);
+        if flags & !FILE_FLAG_ENCRYPTED != 0 {
+            return Err(anyhow!("storage header flags {:08x} unsupported", flags));
+        }
+
+        let mut key_id = None;
+        if flags & FILE_FLAG_ENCRYPTED != 0 {
+            let check = body
+                .get(12..12 + KEY_CHECK_SIZE)
+                .ok_or_else(|| anyhow!("storage header corrupted"))?;
+
+            let key = match (keyring.find_check(check), keyring.current()) {
+                (Some(v), _) => v,
+                (None, Some(_)) => {
+                    return Err(anyhow!(
+                        "data file is encrypted with a different key (key id {:08x}), check the encryption key",
+                        u32::from_le_bytes(check[0..4].try_into()?)
+                    ))
+                }
+                (None, None) => {
+                    return Err(anyhow!(
+                        "data file is encrypted but no encryption key is configured"
+                    ))
+                }
+            };
+            key_id = Some(key.id());
+        }
+
+        return Ok(FileFormat::Binary { header_len, key_id });
+    }
+
+    let mut legacy = [0_u8; 32];
+    legacy[0..4].copy_from_slice(&magic);
+    file.read_exact(&mut legacy[4..])?;
+
+    let check_code = String::from_utf8_lossy(&legacy).to_string();
+
+    if crate::COMPATIBLE_VERSION.contains(&check_code) {
+        return Ok(FileFormat::Legacy);
+    }
+
+    Err(anyhow!("database storage structure unsupported"))
+}
+
+/// 将一条记录编码为 `| LEN | FLAGS | CRC | PAYLOAD |`，按 `codec` 压缩较大的记录，配置了密钥时加密
+pub(crate) fn encode_record(node: &DataNode, codec: Codec) -> Vec<u8> {
+    encode_record_with(node, codec, crypto::keyring().current())
+}
+
+fn encode_record_with(node: &DataNode, codec: Codec, key: Option<&Key>) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(64);
+    encode_node(node, &mut payload);
+
+    let mut flags = 0_u8;
+    if let Some((flag, compressed)) = codec.compress(&payload) {
+        flags |= flag;
+        payload = compressed;
+    }
+
+    if let Some(key) = key {
+        flags |= FLAG_ENCRYPTED;
+        payload = key.seal(&payload);
+    }
+
+    build_record(flags, &payload)
+}
+
+fn build_record(flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(RECORD_HEADER_SIZE as usize + payload.len());
+    buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buf.push(flags);
+
+    let mut digest = CASTAGNOLI.digest();
+    digest.update(&buf[0..5]);
+    digest.update(payload);
+    buf.extend_from_slice(&digest.finalize().to_le_bytes());
+
+    buf.extend_from_slice(payload);
+
+    buf
+}
+
+/// 检查记录长度与校验码，返回记录头中的 FLAGS 与 CRC
+fn check_record(buf: &[u8]) -> crate::Result<(u8, u32)> {
+    if (buf.len() as u64) < RECORD_HEADER_SIZE {
+        return Err(anyhow!("record too short"));
+    }
+
+    let len = u32::from_le_bytes(buf[0..4].try_into()?) as usize;
+    let crc = u32::from_le_bytes(buf[5..9].try_into()?);
+
+    if buf.len() != RECORD_HEADER_SIZE as usize + len {
+        return Err(anyhow!("record length mismatch"));
+    }
+
+    let mut digest = CASTAGNOLI.digest();
+    digest.update(&buf[0..5]);
+    digest.update(&buf[9..]);
+    let computed = digest.finalize();
+
+    if computed != crc {
+        return Err(anyhow!(
+            "checksum mismatch (stored {:08x}, computed {:08x})",
+            crc,
+            computed
+        ));
+    }
+
+    let flags = buf[4];
+    if flags & !(FLAG_COMPRESSION | FLAG_ENCRYPTED) != 0 {
+        return Err(anyhow!("unknown record flags {:02x}", flags));
+    }
+
+    Ok((flags, crc))
+}
+
+/// 解码一条完整的记录（含记录头），校验失败时返回错误
+pub(crate) fn decode_record(buf: &[u8]) -> crate::Result<DataNode> {
+    decode_record_with(buf, &crypto::keyring())
+}
+
+fn decode_record_with(buf: &[u8], keyring: &Keyring) -> crate::Result<DataNode> {
+    let (flags, crc) = check_record(buf)?;
+
+    let mut payload = std::borrow::Cow::Borrowed(&buf[9..]);
+
+    if flags & FLAG_ENCRYPTED != 0 {
+        payload = std::borrow::Cow::Owned(keyring.open(&payload)?);
+    }
+
+    if flags & FLAG_COMPRESSION != 0 {
+        payload = std::borrow::Cow::Owned(decompress(flags & FLAG_COMPRESSION, &payload)?);
+    }
+
+    let mut reader = Reader {
+        buf: &payload,
+        pos: 0,
+    };
+    let node = decode_node(&mut reader, crc)?;
+
+    if reader.pos != payload.len() {
+        return Err(anyhow!("record has trailing data"));
+    }
+
+    Ok(node)
+}
+
+/// 使用当前密钥重新加密一条记录（合并时调用），已经使用当前密钥或没有配置密钥时返回 None
+///
+/// 只替换加密层，压缩方式保持不变。
+pub(crate) fn rekey_record(buf: &[u8]) -> crate::Result<Option<Vec<u8>>> {
+    rekey_record_with(buf, &crypto::keyring())
+}
+
+fn rekey_record_with(buf: &[u8], keyring: &Keyring) -> crate::Result<Option<Vec<u8>>> {
+    let current = match keyring.current() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let (flags, _) = check_record(buf)?;
+    let payload = &buf[9..];
+
+    let plain = if flags & FLAG_ENCRYPTED != 0 {
+        if payload.len() >= 4 && Keyring::key_id(payload) == current.id() {
+            return Ok(None);
+        }
+        keyring.open(payload)?
+    } else {
+        payload.to_vec()
+    };
+
+    Ok(Some(build_record(flags | FLAG_ENCRYPTED, &current.seal(&plain))))
+}
+
+fn encode_node(node: &DataNode, buf: &mut Vec<u8>) {
+    write_bytes(buf, node.key.as_bytes());
+    buf.extend_from_slice(&node.time_stamp.0.to_le_bytes());
+    write_varint(buf, node.time_stamp.1);
+    encode_value(&node.value, buf);
+}
+
+fn decode_node(reader: &mut Reader, crc: u32) -> crate::Result<DataNode> {
+    let key = String::from_utf8(reader.bytes()?.to_vec())?;
+    let write_time = i64::from_le_bytes(reader.take(8)?.try_into()?);
+    let expire = reader.varint()?;
+    let value = decode_value(reader, 0)?;
+
+    let mut node = DataNode::new(key, value, (write_time, expire));
+    node.crc = crc;
+
+    Ok(node)
+}
+
+fn encode_value(value: &DataValue, buf: &mut Vec<u8>) {
+    match value {
+        DataValue::None => buf.push(0),
+        DataValue::String(s) => {
+            buf.push(1);
+            write_bytes(buf, s.as_bytes());
+        }
+        DataValue::Number(n) => {
+            buf.push(2);
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+        DataValue::Boolean(b) => {
+            buf.push(3);
+            buf.push(*b as u8);
+        }
+        DataValue::List(l) => {
+            buf.push(4);
+            write_varint(buf, l.len() as u64);
+            for item in l {
+                encode_value(item, buf);
+            }
+        }
+        DataValue::Dict(d) => {
+            buf.push(5);
+            write_varint(buf, d.len() as u64);
+            for (k, v) in d {
+                write_bytes(buf, k.as_bytes());
+                encode_value(v, buf);
+            }
+        }
+        DataValue::Tuple(t) => {
+            buf.push(6);
+            encode_value(&t.0, buf);
+            encode_value(&t.1, buf);
+        }
+        DataValue::Binary(b) => {
+            buf.push(7);
+            write_bytes(buf, &b.read());
+        }
+    }
+}
+
+// 嵌套层数上限，防止损坏的数据导致栈溢出
+const MAX_VALUE_DEPTH: usize = 128;
+
+fn decode_value(reader: &mut Reader, depth: usize) -> crate::Result<DataValue> {
+    if depth > MAX_VALUE_DEPTH {
+        return Err(anyhow!("value nested too deep"));
+    }
+
+    Ok(match reader.u8()? {
+        0 => DataValue::None,
+        1 => DataValue::String(String::from_utf8(reader.bytes()?.to_vec())?),
+        2 => DataValue::Number(f64::from_le_bytes(reader.take(8)?.try_into()?)),
+        3 => DataValue::Boolean(reader.u8()? != 0),
+        4 => {
+            let len = reader.varint()? as usize;
+            let mut list = Vec::with_capacity(len.min(1024));
+            for _ in 0..len {
+                list.push(decode_value(reader, depth + 1)?);
+            }
+            DataValue::List(list)
+        }
+        5 => {
+            let len = reader.varint()? as usize;
+            let mut dict = std::collections::HashMap::with_capacity(len.min(1024));
+            for _ in 0..len {
+                let k = String::from_utf8(reader.bytes()?.to_vec())?;
+                dict.insert(k, decode_value(reader, depth + 1)?);
+            }
+            DataValue::Dict(dict)
+        }
+        6 => {
+            let a = decode_value(reader, depth + 1)?;
+            let b = decode_value(reader, depth + 1)?;
+            DataValue::Tuple((Box::new(a), Box::new(b)))
+        }
+        7 => DataValue::Binary(Binary::build(reader.bytes()?.to_vec())),
+        tag => return Err(anyhow!("unknown value tag {}", tag)),
+    })
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        buf.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    buf.push(v as u8);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> crate::Result<&'a [u8]> {
+        if len > self.buf.len() - self.pos {
+            return Err(anyhow!("record payload truncated"));
+        }
+        let v = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(v)
+    }
+
+    fn u8(&mut self) -> crate::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> crate::Result<u64> {
+        let mut result = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.u8()?;
+            result |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+        Err(anyhow!("varint overflow"))
+    }
+
+    fn bytes(&mut self) -> crate::Result<&'a [u8]> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+}
+
+/// 数据文件中读出的一条记录
+pub(crate) enum ScannedRecord {
+    Node {
+        position: u64,
+        /// 记录总长度（含记录头）
+        len: u64,
+        node: DataNode,
+    },
+    /// 记录完整但内容校验失败或无法解析
+    Unreadable {
+        position: u64,
+        len: u64,
+        reason: String,
+    },
+    /// 文件末尾不完整的半条记录
+    Torn { position: u64, len: u64 },
+    /// 长度字段超出单条记录上限（已损坏），之后的 `len` 字节无法再按记录切分，其中可能还有完整的记录
+    BadLength { position: u64, len: u64 },
+}
+
+/// 数据文件顺序读取器
+pub(crate) struct RecordReader {
+    reader: BufReader<fs::File>,
+    position: u64,
+    file_size: u64,
+    legacy: bool,
+    buf: Vec<u8>,
+}
+
+impl RecordReader {
+    pub(crate) fn open(path: &Path) -> crate::Result<Self> {
+        let (header_len, legacy) = match detect_format(path)? {
+            FileFormat::Binary { header_len, .. } => (header_len, false),
+            FileFormat::Legacy => (LEGACY_HEADER_SIZE, true),
+        };
+
+        let file = fs::File::open(path)?;
+        let file_size = file.metadata()?.len();
+
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(header_len))?;
+
+        Ok(Self {
+            reader,
+            position: header_len,
+            file_size,
+            legacy,
+            buf: vec![],
+        })
+    }
+
+    /// 只读取前 `size` 字节（之后正在写入的部分不参与扫描，只对二进制格式有效）
+    pub(crate) fn limit(&mut self, size: u64) {
+        self.file_size = self.file_size.min(size);
+    }
+
+    pub(crate) fn next_record(&mut self) -> crate::Result<Option<ScannedRecord>> {
+        if self.legacy {
+            return self.next_legacy_record();
+        }
+
+        let position = self.position;
+        let remain = self.file_size.saturating_sub(position);
+
+        if remain == 0 {
+            return Ok(None);
+        }
+
+        if remain < RECORD_HEADER_SIZE {
+            self.position = self.file_size;
+            return Ok(Some(ScannedRecord::Torn {
+                position,
+                len: remain,
+            }));
+        }
+
+        let mut header = [0_u8; RECORD_HEADER_SIZE as usize];
+        self.reader.read_exact(&mut header)?;
+
+        let payload_len = u32::from_le_bytes(header[0..4].try_into()?) as u64;
+        let len = RECORD_HEADER_SIZE + payload_len;
+
+        // 长度超出上限：长度字段已损坏，不能当作半条记录截断
+        if len > MAX_RECORD_SIZE {
+            self.position = self.file_size;
+            return Ok(Some(ScannedRecord::BadLength {
+                position,
+                len: remain,
+            }));
+        }
+
+        // 长度超出文件剩余部分：写入中途崩溃留下的半条记录
+        if len > remain {
+            self.position = self.file_size;
+            return Ok(Some(ScannedRecord::Torn {
+                position,
+                len: remain,
+            }));
+        }
+
+        self.buf.clear();
+        self.buf.extend_from_slice(&header);
+        self.buf.resize(len as usize, 0);
+        self.reader
+            .read_exact(&mut self.buf[RECORD_HEADER_SIZE as usize..])?;
+
+        self.position += len;
+
+        Ok(Some(match decode_record(&self.buf) {
+            Ok(node) => ScannedRecord::Node {
+                position,
+                len,
+                node,
+            },
+            Err(e) => ScannedRecord::Unreadable {
+                position,
+                len,
+                reason: e.to_string(),
+            },
+        }))
+    }
+
+    /// 旧版本：按 CRLF 切分 JSON 记录（JSON 内部的换行符均已转义）
+    fn next_legacy_record(&mut self) -> crate::Result<Option<ScannedRecord>> {
+        self.buf.clear();
+
+        let len = self.reader.read_until(b'\n', &mut self.buf)?;
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let position = self.position;
+        self.position += len as u64;
+
+        if !self.buf.ends_with(b"\r\n") {
+            return Ok(Some(ScannedRecord::Torn {
+                position,
+                len: len as u64,
+            }));
+        }
+
+        let body = &self.buf[..len - 2];
+
+        // 旧版本的校验码保存在 JSON 内部，由调用方通过 `DataNode::verify` 检查
+        Ok(Some(match serde_json::from_slice::<DataNode>(body) {
+            Ok(node) => ScannedRecord::Node {
+                position,
+                len: len as u64,
+                node,
+            },
+            Err(e) => ScannedRecord::Unreadable {
+                position,
+                len: len as u64,
+                reason: e.to_string(),
+            },
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::value_checksum;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let mut dict = HashMap::new();
+        dict.insert("name".to_string(), DataValue::String("多语言\r\n".into()));
+        dict.insert(
+            "list".to_string(),
+            DataValue::List(vec![DataValue::Number(1.5), DataValue::Boolean(true)]),
+        );
+
+        let values = vec![
+            DataValue::None,
+            DataValue::Number(-3.25),
+            DataValue::Dict(dict),
+            DataValue::Tuple((
+                Box::new(DataValue::String("a".into())),
+                Box::new(DataValue::Binary(Binary::build(vec![0, 13, 10, 255]))),
+            )),
+        ];
+
+        for value in values {
+            let node = DataNode::new("key".into(), value.clone(), (1_700_000_000, 60));
+            let buf = encode_record(&node, Codec::default());
+            let decoded = decode_record(&buf).unwrap();
+
+            // 字典遍历顺序不固定，使用规范化校验码比较
+            assert_eq!(value_checksum(&decoded.value), value_checksum(&value));
+            assert_eq!(decoded.timestamp(), (1_700_000_000, 60));
+        }
+    }
+
+    #[test]
+    fn test_record_detects_corruption() {
+        let node = DataNode::new(
+            "key".into(),
+            DataValue::String("hello".into()),
+            (1_700_000_000, 0),
+        );
+        let buf = encode_record(&node, Codec::default());
+
+        for i in 0..buf.len() {
+            let mut broken = buf.clone();
+            broken[i] ^= 0x01;
+            assert!(decode_record(&broken).is_err(), "byte {} flip undetected", i);
+        }
+
+        assert!(decode_record(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_record_compression() {
+        let text = "dorea ".repeat(1024);
+        let node = DataNode::new("key".into(), DataValue::String(text.clone()), (1_700_000_000, 0));
+        let plain = encode_record(&node, Codec::default());
+
+        for compression in [Compression::Zstd, Compression::Lz4] {
+            let buf = encode_record(&node, Codec::new(compression, 0));
+            assert_ne!(buf[4] & FLAG_COMPRESSION, 0);
+            assert!(buf.len() < plain.len());
+            assert_eq!(logical_size(&node), plain.len() as u64);
+
+            let decoded = decode_record(&buf).unwrap();
+            assert_eq!(decoded.value, DataValue::String(text.clone()));
+
+            // 低于阈值的记录不压缩
+            let small = DataNode::new("key".into(), DataValue::Number(1.0), (1_700_000_000, 0));
+            assert_eq!(encode_record(&small, Codec::new(compression, 0))[4], 0);
+        }
+    }
+
+    #[test]
+    fn test_record_encryption() {
+        let old = Keyring::parse(&base64::encode([1_u8; 32])).unwrap();
+        let new = Keyring::parse(&format!("{} {}", base64::encode([2_u8; 32]), base64::encode([1_u8; 32])))
+            .unwrap();
+
+        let text = "dorea ".repeat(1024);
+        let node = DataNode::new("key".into(), DataValue::String(text.clone()), (1_700_000_000, 0));
+
+        let buf = encode_record_with(&node, Codec::new(Compression::Zstd, 0), old.current());
+        assert_eq!(buf[4], FLAG_ENCRYPTED | FLAG_ZSTD);
+        assert!(!buf.windows(5).any(|v| v == b"dorea"));
+        assert_eq!(decode_record_with(&buf, &old).unwrap().value, DataValue::String(text.clone()));
+        assert!(decode_record_with(&buf, &Keyring::default()).is_err());
+
+        // 轮换：旧密钥写入的记录使用新密钥重新加密，压缩方式不变
+        let rekeyed = rekey_record_with(&buf, &new).unwrap().unwrap();
+        assert_eq!(rekeyed[4], FLAG_ENCRYPTED | FLAG_ZSTD);
+        assert!(rekey_record_with(&rekeyed, &new).unwrap().is_none());
+        assert!(decode_record_with(&rekeyed, &old).is_err());
+        assert_eq!(decode_record_with(&rekeyed, &new).unwrap().value, DataValue::String(text));
+
+        // 文件头中的密钥校验值不匹配时给出明确的错误
+        let path = std::env::temp_dir().join(format!("dorea-encrypted-{}.db", std::process::id()));
+        fs::write(&path, file_header_with(old.current())).unwrap();
+
+        assert!(matches!(
+            detect_format_with(&path, &new).unwrap(),
+            FileFormat::Binary { header_len: 32, key_id: Some(_) }
+        ));
+        let wrong = Keyring::parse(&base64::encode([3_u8; 32])).unwrap();
+        let err = detect_format_with(&path, &wrong).unwrap_err().to_string();
+        assert!(err.contains("different key"), "{}", err);
+        let err = detect_format_with(&path, &Keyring::default()).unwrap_err().to_string();
+        assert!(err.contains("no encryption key"), "{}", err);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reader_detects_torn_tail() {
+        let path = std::env::temp_dir().join(format!("dorea-torn-{}.db", std::process::id()));
+
+        let node = DataNode::new("key".into(), DataValue::Number(1.0), (1_700_000_000, 0));
+        let record = encode_record(&node, Codec::default());
+
+        let mut content = file_header();
+        content.extend_from_slice(&record);
+        content.extend_from_slice(&record[..record.len() - 3]);
+        fs::write(&path, &content).unwrap();
+
+        let mut reader = RecordReader::open(&path).unwrap();
+
+        assert!(matches!(
+            reader.next_record().unwrap(),
+            Some(ScannedRecord::Node { position, len, .. })
+                if position == FILE_HEADER_SIZE && len == record.len() as u64
+        ));
+
+        let torn_at = FILE_HEADER_SIZE + record.len() as u64;
+        assert!(matches!(
+            reader.next_record().unwrap(),
+            Some(ScannedRecord::Torn { position, len })
+                if position == torn_at && len == record.len() as u64 - 3
+        ));
+        assert!(reader.next_record().unwrap().is_none());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
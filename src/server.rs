@@ -183,7 +183,7 @@ impl DoreaServer {
 
                 // connection number -1;
                 connect_num.lock().await.low();
-                DB_STATISTICS.lock().await.remove(&connid);
+                db_stat_remove(connid).await;
             });
         }
     }
@@ -218,6 +218,10 @@ pub async fn db_stat_set(connid: uuid::Uuid, db_name: String) {
     DB_STATISTICS.lock().await.insert(connid, db_name);
 }
 
+pub async fn db_stat_remove(connid: uuid::Uuid) {
+    DB_STATISTICS.lock().await.remove(&connid);
+}
+
 pub async fn db_stat_exist(db_name: String) -> bool {
     for (_, v) in DB_STATISTICS.lock().await.iter() {
         if *v == db_name {
@@ -0,0 +1,180 @@
+//! 库快照
+//!
+//! 快照目录中包含冻结时刻的所有归档文件（及其 hint 文件）和一份 `manifest.json`，
+//! 清单里记录了快照时的文件编号以及每个文件的大小和 CRC32C 校验码，
+//! 恢复前会先完整校验，保证快照可以脱离原库单独使用。
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::database::CASTAGNOLI;
+
+pub(crate) const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Manifest {
+    pub(crate) group: String,
+    pub(crate) created_at: i64,
+    pub(crate) dorea_version: String,
+    pub(crate) storage_format: u16,
+    /// 快照时的活跃文件编号，恢复后从这里继续写入
+    pub(crate) file_id: u32,
+    pub(crate) files: Vec<ManifestFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestFile {
+    pub(crate) name: String,
+    pub(crate) size: u64,
+    pub(crate) crc: u32,
+}
+
+/// 快照中只允许出现归档文件和 hint 文件
+fn valid_file_name(name: &str) -> bool {
+    let id = name
+        .strip_prefix("archive-")
+        .and_then(|v| v.strip_suffix(".db").or_else(|| v.strip_suffix(".hint")));
+
+    matches!(id, Some(v) if !v.is_empty() && v.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// 计算文件的大小与校验码，指定 `target` 时同时复制（复制完成后落盘）
+fn checksum(source: &Path, target: Option<&Path>) -> crate::Result<(u64, u32)> {
+    let mut input = fs::File::open(source)?;
+    let mut output = match target {
+        Some(v) => Some(fs::File::create(v)?),
+        None => None,
+    };
+
+    let mut digest = CASTAGNOLI.digest();
+    let mut size = 0_u64;
+    let mut buf = vec![0_u8; 1024 * 256];
+
+    loop {
+        let len = input.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        digest.update(&buf[..len]);
+        if let Some(out) = output.as_mut() {
+            out.write_all(&buf[..len])?;
+        }
+        size += len as u64;
+    }
+
+    if let Some(out) = output {
+        out.sync_all()?;
+    }
+
+    Ok((size, digest.finalize()))
+}
+
+/// 将冻结的文件从 `staging` 复制到快照目录，并写出清单
+pub(crate) fn write(
+    staging: &Path,
+    target: &Path,
+    group: &str,
+    file_id: u32,
+) -> crate::Result<Manifest> {
+    fs::create_dir_all(target)?;
+
+    let mut names: Vec<String> = fs::read_dir(staging)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .filter(|v| valid_file_name(v))
+        .collect();
+    names.sort();
+
+    let mut files = vec![];
+
+    for name in names {
+        let (size, crc) = checksum(&staging.join(&name), Some(&target.join(&name)))?;
+        files.push(ManifestFile { name, size, crc });
+    }
+
+    let manifest = Manifest {
+        group: group.to_string(),
+        created_at: chrono::Local::now().timestamp(),
+        dorea_version: crate::DOREA_VERSION.to_string(),
+        storage_format: crate::STORAGE_FORMAT_VERSION,
+        file_id,
+        files,
+    };
+
+    // 清单最后写入：没有清单的目录不是完整的快照
+    let temp = target.join(format!("{}.tmp", MANIFEST_NAME));
+    fs::write(&temp, serde_json::to_vec_pretty(&manifest)?)?;
+    fs::File::open(&temp)?.sync_all()?;
+    fs::rename(&temp, target.join(MANIFEST_NAME))?;
+
+    Ok(manifest)
+}
+
+/// 读取清单并校验快照中的每个文件
+pub(crate) fn verify(path: &Path) -> crate::Result<Manifest> {
+    let manifest = fs::read(path.join(MANIFEST_NAME))
+        .map_err(|e| anyhow!("snapshot manifest not found: {}", e))?;
+    let manifest: Manifest = serde_json::from_slice(&manifest)
+        .map_err(|e| anyhow!("snapshot manifest is invalid: {}", e))?;
+
+    if manifest.storage_format > crate::STORAGE_FORMAT_VERSION {
+        return Err(anyhow!(
+            "snapshot storage format version {} unsupported",
+            manifest.storage_format
+        ));
+    }
+
+    for file in manifest.files.iter() {
+        if !valid_file_name(&file.name) {
+            return Err(anyhow!("snapshot file `{}` is not allowed", file.name));
+        }
+
+        let (size, crc) = checksum(&path.join(&file.name), None)
+            .map_err(|e| anyhow!("snapshot file `{}` unreadable: {}", file.name, e))?;
+
+        if size != file.size || crc != file.crc {
+            return Err(anyhow!("snapshot file `{}` checksum mismatch", file.name));
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// 校验快照后将其中的文件复制到 `target`（复制过程中再次校验）
+pub(crate) fn restore(path: &Path, target: &Path) -> crate::Result<Manifest> {
+    let manifest = verify(path)?;
+
+    if target.exists() {
+        fs::remove_dir_all(target)?;
+    }
+    fs::create_dir_all(target)?;
+
+    for file in manifest.files.iter() {
+        let (size, crc) = checksum(&path.join(&file.name), Some(&target.join(&file.name)))?;
+
+        if size != file.size || crc != file.crc {
+            return Err(anyhow!("snapshot file `{}` changed during restore", file.name));
+        }
+    }
+
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_file_names() {
+        assert!(valid_file_name("archive-1.db"));
+        assert!(valid_file_name("archive-12.hint"));
+        assert!(!valid_file_name("active.db"));
+        assert!(!valid_file_name("archive-.db"));
+        assert!(!valid_file_name("archive-1/../x.db"));
+        assert!(!valid_file_name("manifest.json"));
+    }
+}
@@ -0,0 +1,1565 @@
+//! 日志存储
+//!
+//! 记录追加写入库目录中的活跃文件 `active.db`，写满后归档为 `archive-<编号>.db`，
+//! 归档时根据内存索引生成 hint 文件，加载时优先读取 hint 而不是扫描整个文件。
+//! 合并在锁外把仍然有效的记录复制到 `merge` 目录，完成后替换原有的归档文件。
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use ::log::info;
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use serde::Serialize;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use super::StorageBackend;
+use crate::configure::{Durability, StorageKind};
+use crate::crypto;
+use crate::database::{
+    apply_entries, compact_entries, expire_deadline, index_entry_size, is_expired, write_atomic, Access,
+    DataNode, GroupState, IndexInfo, LoadProgress, MergeProgress, GROUP_CONFIG_FILE, TOTAL_INDEX_NUMBER,
+};
+use crate::hint::{self, HintEntry};
+use crate::reader::ReadPool;
+use crate::record::{self, Codec, FileFormat, RecordReader, ScannedRecord};
+use crate::value::DataValue;
+
+// 活跃文件达到该大小后归档
+const ARCHIVE_SIZE: u64 = 1024 * 1024 * 64;
+
+/// 缓存的文件写入器，避免每次写入都打开文件
+struct DataFileWriter {
+    file: tokio::fs::File,
+    /// 当前写入位置，避免每次调用 metadata()
+    write_position: u64,
+}
+
+impl std::fmt::Debug for DataFileWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataFileWriter")
+            .field("write_position", &self.write_position)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 日志存储：记录追加写入活跃文件，写满后归档，合并时重写归档文件
+#[derive(Debug)]
+pub(crate) struct LogStorage {
+    root: PathBuf,
+    /// 缓存的读取句柄
+    readers: Arc<ReadPool>,
+    /// 活跃文件的写入句柄和写入位置（只有写入任务使用）
+    writer: Mutex<Option<DataFileWriter>>,
+    /// 活跃文件中最后一次操作为删除的 key，归档时写入 hint 文件
+    tombstones: std::sync::Mutex<HashMap<String, HintEntry>>,
+}
+
+impl LogStorage {
+    pub(crate) fn new(root: &Path, mmap: bool) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            readers: Arc::new(ReadPool::new(mmap)),
+            writer: Mutex::new(None),
+            tombstones: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 当前目录下所有归档文件编号（升序）
+    fn archive_ids(&self) -> Vec<u32> {
+        archive_ids(self.root())
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// 顺序扫描数据文件中的所有记录，`scanned` 累加已读取的字节数，同时返回末尾无法解析的部分（半条记录或损坏的长度字段）
+    fn scan_data_file(path: &Path, scanned: &AtomicU64) -> crate::Result<(Vec<HintEntry>, Option<ScannedRecord>)> {
+        let mut reader = match RecordReader::open(path) {
+            Ok(v) => v,
+            Err(_) => return Ok((vec![], None)),
+        };
+
+        let mut result = vec![];
+        let mut tail = None;
+
+        while let Some(record) = reader.next_record()? {
+            let (position, len, node) = match record {
+                ScannedRecord::Node {
+                    position,
+                    len,
+                    node,
+                } => (position, len, node),
+                ScannedRecord::Unreadable {
+                    position, reason, ..
+                } => {
+                    // 长度完整但内容损坏：无法得知 key，跳过这条记录继续向后扫描
+                    log::error!("corrupt record found in {:?} at {}: {}.", path, position, reason);
+                    continue;
+                }
+                ScannedRecord::Torn { position, .. } => {
+                    log::warn!("incomplete record found in {:?} at {}.", path, position);
+                    tail = Some(record);
+                    break;
+                }
+                ScannedRecord::BadLength { position, .. } => {
+                    log::error!("damaged record length found in {:?} at {}.", path, position);
+                    tail = Some(record);
+                    break;
+                }
+            };
+
+            scanned.fetch_add(len, Ordering::Relaxed);
+
+            result.push(HintEntry {
+                tombstone: node.value == DataValue::None,
+                key: node.key,
+                start_position: position,
+                end_position: position + len,
+                time_stamp: node.time_stamp,
+            });
+        }
+
+        Ok((result, tail))
+    }
+
+    /// 将活跃文件截断到 `position`（加载时调用，此时还没有打开写入句柄）
+    ///
+    /// 长度字段损坏或者指向文件末尾之后时，无法区分半条记录与之后仍然完整的记录，
+    /// 因此只有不足一个记录头的碎片直接丢弃，其余部分截断前先复制到 `active.db.corrupt-<毫秒时间戳>` 中保留。
+    fn truncate_active(&self, position: u64) -> crate::Result<()> {
+        let path = self.root().join("active.db");
+
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        if size.saturating_sub(position) >= record::RECORD_HEADER_SIZE {
+            let sidecar = self.root().join(format!(
+                "active.db.corrupt-{}",
+                chrono::Local::now().timestamp_millis()
+            ));
+
+            let mut out = fs::File::create(&sidecar)?;
+            file.seek(SeekFrom::Start(position))?;
+            std::io::copy(&mut file, &mut out)?;
+            out.sync_all()?;
+
+            log::error!(
+                "{} bytes after the incomplete or damaged record at {} of {:?} saved to {:?}.",
+                size.saturating_sub(position),
+                position,
+                path,
+                sidecar,
+            );
+        }
+
+        file.set_len(position)?;
+        file.sync_all()?;
+
+        log::warn!(
+            "{:?} truncated to {} bytes, {} bytes of incomplete record cut off.",
+            path,
+            position,
+            size.saturating_sub(position),
+        );
+
+        Ok(())
+    }
+
+    /// 将归档文件末尾无法解析的部分复制到 `archive-<编号>.db.corrupt-<毫秒时间戳>` 后从归档文件中去掉
+    ///
+    /// 去掉之后才能重新生成 hint 文件并参与合并；归档文件可能与快照或复制出的库共享（硬链接），
+    /// 因此写入新文件后替换，而不是原地截断。
+    fn cut_archive_tail(&self, path: &Path, position: u64) -> crate::Result<()> {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(format!(".corrupt-{}", chrono::Local::now().timestamp_millis()));
+        let sidecar = PathBuf::from(sidecar);
+
+        let mut out = fs::File::create(&sidecar)?;
+        file.seek(SeekFrom::Start(position))?;
+        std::io::copy(&mut file, &mut out)?;
+        out.sync_all()?;
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".copy");
+        let temp = PathBuf::from(temp);
+
+        let mut out = fs::File::create(&temp)?;
+        file.seek(SeekFrom::Start(0))?;
+        std::io::copy(&mut (&mut file).take(position), &mut out)?;
+        out.sync_all()?;
+        fs::rename(&temp, path)?;
+
+        log::error!(
+            "{} bytes after the incomplete or damaged record at {} of {:?} saved to {:?}.",
+            size.saturating_sub(position),
+            position,
+            path,
+            sidecar,
+        );
+
+        Ok(())
+    }
+
+    /// 检查该库所有数据文件，报告损坏与不完整的记录，返回活跃文件编号
+    pub(crate) fn init_db(&self) -> crate::Result<u32> {
+        if !self.root().is_dir() {
+            fs::create_dir_all(self.root())?;
+        }
+
+        // 上次合并在替换文件时中断：按完成标记继续替换，未完成的合并直接丢弃
+        if install_merge(self.root())? {
+            log::warn!("interrupted merge of {:?} has been completed.", self.root());
+        }
+
+        // 中断的快照留下的硬链接目录
+        for entry in fs::read_dir(self.root())?.filter_map(|e| e.ok()) {
+            if entry.path().is_dir()
+                && entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(SNAPSHOT_STAGING_PREFIX)
+            {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+
+        // 活跃文件编号：record.in 可能落后于已有的归档文件（归档途中崩溃），以两者中较大的为准
+        let record_in = self.root().join("record.in");
+        let stored = fs::read_to_string(&record_in)
+            .ok()
+            .and_then(|v| v.trim().parse::<u32>().ok());
+        let next = self.archive_ids().last().map(|v| v + 1).unwrap_or(1);
+
+        let file_id = stored.unwrap_or(1).max(next);
+
+        if stored != Some(file_id) {
+            if record_in.is_file() {
+                log::warn!(
+                    "{:?} is stale or invalid ({:?}), active file id repaired to {}.",
+                    record_in,
+                    stored,
+                    file_id
+                );
+            }
+            write_atomic(self.root(), "record.in", file_id.to_string().as_bytes())?;
+        }
+
+        let save_file = self.root().join("active.db");
+
+        if !save_file.is_file() {
+            self.active()?;
+        } else if fs::metadata(&save_file)?.len() < record::FILE_HEADER_SIZE {
+            // 创建文件时崩溃，只写入了部分文件头（此时还没有任何记录）
+            log::warn!("{:?} has an incomplete header, recreated.", save_file);
+            self.active()?;
+        }
+
+        let state_json = self.root().join("state.json");
+        if !state_json.is_file() {
+            fs::write(
+                state_json,
+                json!({
+                    "index_number": 0,
+                    "init_version": crate::DOREA_VERSION,
+                    "update_time": chrono::Local::now().timestamp(),
+                })
+                .to_string()
+                .as_bytes(),
+            )?;
+        }
+
+        self.check_db()?;
+
+        self.check_active_key(file_id)
+    }
+
+    /// 活跃文件的加密密钥与当前密钥不一致时（开启加密或轮换了密钥）归档该文件，
+    /// 保证每个数据文件中的记录都使用文件头中的密钥加密，返回新的活跃文件编号
+    fn check_active_key(&self, file_id: u32) -> crate::Result<u32> {
+        let path = self.root().join("active.db");
+
+        let (header_len, key_id) = match record::detect_format(&path)? {
+            FileFormat::Binary { header_len, key_id } => (header_len, key_id),
+            FileFormat::Legacy => return Ok(file_id),
+        };
+
+        if key_id == crypto::keyring().current().map(|v| v.id()) {
+            return Ok(file_id);
+        }
+
+        let mut file_id = file_id;
+
+        if fs::metadata(&path)?.len() > header_len {
+            fs::rename(&path, self.root().join(format!("archive-{}.db", file_id)))?;
+            file_id += 1;
+            write_atomic(self.root(), "record.in", file_id.to_string().as_bytes())?;
+        }
+        self.active()?;
+
+        log::info!(
+            "active file of {:?} uses another encryption key, new active file {} created.",
+            self.root(),
+            file_id
+        );
+
+        Ok(file_id)
+    }
+
+    /// 检查所有数据文件的存储格式
+    ///
+    /// 旧版本（`COMPATIBLE_VERSION`）的 JSON 数据文件在这里一次性迁移为二进制格式，
+    /// 每个文件单独转换并重命名替换，迁移中途退出后下次启动会继续处理剩余的文件。
+    fn check_db(&self) -> crate::Result<()> {
+        let mut files: Vec<(Option<u32>, PathBuf)> = self
+            .archive_ids()
+            .into_iter()
+            .map(|id| (Some(id), self.root().join(format!("archive-{}.db", id))))
+            .collect();
+        files.push((None, self.root().join("active.db")));
+
+        for (archive_id, path) in files {
+            match record::detect_format(&path) {
+                Ok(FileFormat::Binary { .. }) => {}
+                Ok(FileFormat::Legacy) => self.migrate_file(&path, archive_id)?,
+                Err(e) => return Err(anyhow!("{:?}: {}", path, e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 将旧版本 JSON 数据文件转换为当前的二进制格式
+    fn migrate_file(&self, path: &Path, archive_id: Option<u32>) -> crate::Result<()> {
+        let temp = path.with_extension("db.migrate");
+
+        let mut reader = RecordReader::open(path)?;
+        let mut out = std::io::BufWriter::new(fs::File::create(&temp)?);
+
+        out.write_all(&record::file_header())?;
+
+        let mut migrated = 0_usize;
+        let mut dropped = 0_usize;
+
+        while let Some(scanned) = reader.next_record()? {
+            match scanned {
+                ScannedRecord::Node { position, node, .. } => {
+                    let mut buf = record::encode_record(&node, Codec::default());
+
+                    // 原本就校验失败的记录保留为损坏状态，读取时继续返回损坏错误而不是被悄悄“修复”
+                    if let Err(e) = node.verify() {
+                        log::error!("corrupt record migrated from {:?} at {}: {}.", path, position, e);
+                        buf[5] ^= 0xFF;
+                    }
+
+                    out.write_all(&buf)?;
+                    migrated += 1;
+                }
+                ScannedRecord::Unreadable {
+                    position, reason, ..
+                } => {
+                    log::error!("unreadable record dropped from {:?} at {}: {}.", path, position, reason);
+                    dropped += 1;
+                }
+                ScannedRecord::Torn { position, len } | ScannedRecord::BadLength { position, len } => {
+                    log::warn!("incomplete record dropped from {:?} at {} ({} bytes).", path, position, len);
+                    dropped += 1;
+                }
+            }
+        }
+
+        let out = out.into_inner().map_err(|e| anyhow!(e.to_string()))?;
+        out.sync_all()?;
+        drop(out);
+
+        fs::rename(&temp, path)?;
+
+        // 记录位置已经变化，旧的 hint 文件作废（加载时重新生成）
+        if let Some(id) = archive_id {
+            let _ = fs::remove_file(hint::hint_path(self.root(), id));
+        }
+
+        info!(
+            "storage file {:?} migrated to format v{} [{} records, {} dropped].",
+            path,
+            crate::STORAGE_FORMAT_VERSION,
+            migrated,
+            dropped,
+        );
+
+        Ok(())
+    }
+
+    /// 编码并追加记录，返回每条记录的起止位置
+    async fn append(
+        &self,
+        state: &GroupState,
+        writer: &mut Option<DataFileWriter>,
+        nodes: &[&DataNode],
+    ) -> crate::Result<Vec<(u64, u64)>> {
+        // 检查并处理 archive（如果需要）
+        self.check_and_archive(state, writer).await?;
+
+        let codec = state.codec();
+        let file_path = self.root().join("active.db");
+
+        // 获取或创建 writer
+        let writer = if let Some(w) = writer {
+            w
+        } else {
+            // 首次写入：打开文件并获取当前位置
+            let f = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(&file_path)
+                .await?;
+            let pos = f.metadata().await?.len();
+            if state.commit.mode != Durability::None {
+                state.commit.attach(Arc::new(f.try_clone().await?));
+            }
+            writer.insert(DataFileWriter {
+                file: f,
+                write_position: pos,
+            })
+        };
+
+        // 准备数据
+        let mut buf = vec![];
+        let mut positions = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let start = writer.write_position + buf.len() as u64;
+            buf.extend_from_slice(&record::encode_record(node, codec));
+            positions.push((start, writer.write_position + buf.len() as u64));
+        }
+
+        // 写入数据（flush 等待后台写入完成，保证随后的读取和 fsync 能看到这些记录）
+        writer.file.write_all(&buf).await?;
+        writer.file.flush().await?;
+        writer.write_position += buf.len() as u64;
+
+        state.commit.advance(buf.len() as u64);
+
+        Ok(positions)
+    }
+
+    /// 检查文件是否需要 archive，如果需要则执行
+    /// 返回 true 表示执行了 archive
+    async fn check_and_archive(
+        &self,
+        state: &GroupState,
+        writer: &mut Option<DataFileWriter>,
+    ) -> crate::Result<bool> {
+        let file = self.root().join("active.db");
+
+        // 使用缓存的 write_position 或获取文件大小
+        let size = if let Some(ref writer) = writer {
+            writer.write_position
+        } else {
+            match tokio::fs::metadata(&file).await {
+                Ok(v) => v.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    Self::reset_writer(state, writer);
+                    self.readers.invalidate(state.file_id());
+                    let root = self.root.clone();
+                    tokio::task::spawn_blocking(move || {
+                        write_atomic(&root, "active.db", &record::file_header())
+                    })
+                    .await??;
+                    return Ok(false);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        };
+
+        if size >= ARCHIVE_SIZE {
+            self.rotate_active(state, writer).await?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// 将活跃文件归档（活跃文件中没有记录时跳过）
+    async fn rotate_active(
+        &self,
+        state: &GroupState,
+        writer: &mut Option<DataFileWriter>,
+    ) -> crate::Result<()> {
+        let file = self.root().join("active.db");
+
+        if tokio::fs::metadata(&file).await?.len() <= record::header_size() {
+            return Ok(());
+        }
+
+        // archive 前先落盘并关闭文件句柄，归档文件之后不会再被 fsync
+        if let Some(writer) = writer.take() {
+            if state.commit.mode != Durability::None {
+                writer.file.sync_data().await?;
+            }
+        }
+        state.commit.detach();
+        self.archive(state).await
+    }
+
+    /// 丢弃缓存的写入句柄（数据文件被外部替换或删除时调用）
+    fn reset_writer(state: &GroupState, writer: &mut Option<DataFileWriter>) {
+        *writer = None;
+        state.commit.detach();
+    }
+
+    /// 创建新的活跃文件（先写入临时文件再重命名，崩溃时不会留下只有部分文件头的文件）
+    fn active(&self) -> crate::Result<()> {
+        write_atomic(self.root(), "active.db", &record::file_header())
+    }
+
+    async fn archive(&self, state: &GroupState) -> crate::Result<()> {
+        let file = self.root().join("active.db");
+
+        let count = state.file_id();
+
+        let data_size = tokio::fs::metadata(&file).await?.len();
+
+        tokio::fs::rename(&file, self.root().join(format!("archive-{}.db", count))).await?;
+
+        // 重命名之后活跃文件编号立即前进，即使下面的步骤失败也不会再写入已归档的编号；
+        // 读取时依赖“先重命名、后更新编号”的顺序判断打开的是否为活跃文件
+        let file_id = count + 1;
+        state.file_id.store(file_id, Ordering::Release);
+
+        // 归档后的文件可以改用内存映射读取
+        self.readers.invalidate(count);
+
+        // 归档文件不会再改变：从内存索引直接生成 hint，无需重新扫描
+        let mut entries: Vec<HintEntry> = state
+            .index
+            .iter()
+            .filter(|info| info.file_id == count)
+            .map(|info| HintEntry {
+                key: info.key().clone(),
+                start_position: info.start_position,
+                end_position: info.end_position,
+                time_stamp: info.time_stamp,
+                tombstone: false,
+            })
+            .collect();
+        entries.extend(self.tombstones.lock().unwrap().drain().map(|(_, v)| v));
+
+        let mut positions: HashSet<u64> = entries.iter().map(|v| v.start_position).collect();
+        entries.extend(
+            state
+                .history
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, v)| v.info.file_id == count && positions.insert(v.info.start_position))
+                .map(|(key, v)| HintEntry {
+                    key: key.clone(),
+                    start_position: v.info.start_position,
+                    end_position: v.info.end_position,
+                    time_stamp: v.info.time_stamp,
+                    tombstone: v.tombstone,
+                }),
+        );
+        entries.sort_by_key(|v| v.start_position);
+
+        let root = self.root.clone();
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = hint::write(&root, count, data_size, &entries) {
+                log::warn!("hint file write failed for archive-{}: {}.", count, e);
+            }
+
+            // 在这两步之间崩溃时，启动时会根据已有的归档文件修正编号并重新创建活跃文件
+            write_atomic(&root, "record.in", file_id.to_string().as_bytes())?;
+            write_atomic(&root, "active.db", &record::file_header())
+        })
+        .await?
+    }
+
+    /// 替换归档文件，并更新合并期间没有被修改过的索引
+    async fn install(&self, state: &GroupState, plan: &MergePlan, output: MergeOutput) -> crate::Result<()> {
+        if plan.epoch != state.epoch.load(Ordering::Acquire) {
+            return Err(anyhow!("group changed during merge"));
+        }
+
+        let root = self.root.clone();
+        tokio::task::spawn_blocking(move || install_merge(&root)).await??;
+        state.epoch.fetch_add(1, Ordering::AcqRel);
+        // 记录位置已经变化，缓存项全部失效
+        state.cache.clear();
+
+        let index = &state.index;
+
+        // 写锁只能阻止新的写入提交，已经在队列中的写入仍可能同时修改索引，
+        // 因此比较和替换需要在同一次分片锁定中完成
+        for (key, old, new) in output.moved {
+            let replaced = match index.get_mut(&key) {
+                Some(mut v) if *v == old => {
+                    // 只更新位置，保留访问记录
+                    v.file_id = new.file_id;
+                    v.start_position = new.start_position;
+                    v.end_position = new.end_position;
+                    true
+                }
+                _ => false,
+            };
+
+            if !replaced {
+                state.history.lock().unwrap().remap(&key, &old, &new);
+            }
+        }
+
+        let mut expired = 0;
+        for (key, old) in output.expired {
+            if state.remove_expired(&key, &old).is_some() {
+                expired += 1;
+            }
+        }
+
+        log::info!(
+            "merge success: {:?} [{} bytes copied, {} expired keys removed].",
+            self.root().file_name().unwrap(),
+            plan.progress.copied_bytes.load(Ordering::Relaxed),
+            expired,
+        );
+
+        Ok(())
+    }
+}
+
+impl StorageBackend for LogStorage {
+    fn kind(&self) -> StorageKind {
+        StorageKind::Log
+    }
+
+    fn load_index(&self, state: &GroupState, progress: &LoadProgress) -> crate::Result<()> {
+        let file_id = self.init_db()?;
+        state.file_id.store(file_id, Ordering::Release);
+
+        let mut index = HashMap::new();
+        let mut history = std::mem::take(&mut *state.history.lock().unwrap());
+
+        let archives: Vec<(u32, PathBuf, u64)> = self
+            .archive_ids()
+            .into_iter()
+            .filter_map(|id| {
+                let path = self.root().join(format!("archive-{}.db", id));
+                fs::metadata(&path).ok().map(|v| (id, path, v.len()))
+            })
+            .collect();
+
+        let active = self.root().join("active.db");
+        let active_size = fs::metadata(&active).map(|v| v.len()).unwrap_or(0);
+
+        progress.begin(archives.iter().map(|v| v.2).sum::<u64>() + active_size);
+
+        // 归档文件必须按编号顺序加载，后写入的记录覆盖先写入的记录
+        for (file_id, path, data_size) in archives {
+            let base = progress.scanned_bytes.load(Ordering::Relaxed);
+
+            let entries = match hint::read(self.root(), file_id, data_size) {
+                Some(v) => v,
+                None => {
+                    // hint 文件缺失或失效：全量扫描后重新生成
+                    let (entries, tail) = Self::scan_data_file(&path, &progress.scanned_bytes)?;
+
+                    // 末尾无法解析的部分先保存副本，否则之后的合并会将其中可能完整的记录永久丢弃
+                    let hint_size = match tail {
+                        Some(ScannedRecord::Torn { position, .. } | ScannedRecord::BadLength { position, .. }) => {
+                            self.cut_archive_tail(&path, position)?;
+                            position
+                        }
+                        _ => data_size,
+                    };
+
+                    let entries = compact_entries(entries, history.depth + 1);
+                    if let Err(e) = hint::write(self.root(), file_id, hint_size, &entries) {
+                        log::warn!("hint file write failed for {:?}: {}.", path, e);
+                    }
+                    entries
+                }
+            };
+
+            apply_entries(&mut index, &mut history, file_id, entries);
+            progress.scanned_bytes.store(base + data_size, Ordering::Relaxed);
+        }
+
+        // 活跃文件始终全量扫描，同时恢复它的删除标记（用于归档时生成 hint）
+        let active_id = state.file_id();
+        let (entries, tail) = Self::scan_data_file(&active, &progress.scanned_bytes)?;
+
+        // 写入中途崩溃会在文件末尾留下半条记录，截断后再继续追加，避免新记录接在残缺数据之后；
+        // 长度字段损坏时之后的数据无法解析，截断前保存一份副本
+        if let Some(ScannedRecord::Torn { position, .. } | ScannedRecord::BadLength { position, .. }) = tail {
+            self.truncate_active(position)?;
+        }
+
+        {
+            let mut tombstones = self.tombstones.lock().unwrap();
+            tombstones.clear();
+            for entry in entries.iter() {
+                if entry.tombstone {
+                    tombstones.insert(entry.key.clone(), entry.clone());
+                } else {
+                    tombstones.remove(&entry.key);
+                }
+            }
+        }
+
+        apply_entries(&mut index, &mut history, active_id, entries);
+
+        let count = index.len();
+        let memory: u64 = index.keys().map(|v| index_entry_size(v)).sum::<u64>() + history.memory;
+
+        *state.expiry.lock().unwrap() = index
+            .iter()
+            .filter_map(|(key, info)| expire_deadline(info.time_stamp).map(|v| (v, key.clone())))
+            .collect();
+        *state.history.lock().unwrap() = history;
+        state.index.clear();
+        for (key, info) in index {
+            state.index.insert(key, info);
+        }
+        state.charge(memory as i64);
+
+        info!(
+            "index information loaded from {:?} [{}].",
+            self.root().file_name().unwrap(),
+            count,
+        );
+        TOTAL_INDEX_NUMBER.fetch_add(count as u32, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    fn write<'a>(
+        &'a self,
+        state: &'a GroupState,
+        nodes: &'a [&'a DataNode],
+    ) -> BoxFuture<'a, crate::Result<Vec<(u64, u64)>>> {
+        Box::pin(async move {
+            let mut writer = self.writer.lock().await;
+
+            let positions = match self.append(state, &mut writer, nodes).await {
+                Ok(v) => v,
+                Err(e) => {
+                    // 写入位置已经不可信，下次写入时重新打开文件
+                    Self::reset_writer(state, &mut writer);
+                    return Err(e);
+                }
+            };
+
+            let mut tombstones = self.tombstones.lock().unwrap();
+            for (node, (start, end)) in nodes.iter().zip(positions.iter()) {
+                if node.value == DataValue::None {
+                    tombstones.insert(
+                        node.key.clone(),
+                        HintEntry {
+                            key: node.key.clone(),
+                            start_position: *start,
+                            end_position: *end,
+                            time_stamp: node.time_stamp,
+                            tombstone: true,
+                        },
+                    );
+                } else {
+                    tombstones.remove(&node.key);
+                }
+            }
+
+            Ok(positions)
+        })
+    }
+
+    fn read(&self, state: &Arc<GroupState>, info: IndexInfo) -> BoxFuture<'static, crate::Result<DataNode>> {
+        let state = state.clone();
+        let readers = self.readers.clone();
+        let root = self.root.clone();
+
+        // 按位置读取是阻塞操作（数据不在页缓存中时需要等待磁盘），放到阻塞线程池中执行
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let corrupted = |reason: String| {
+                    anyhow!(
+                        "data corrupted: file {} offset {}: {}",
+                        info.file_id,
+                        info.start_position,
+                        reason
+                    )
+                };
+
+                let buf = readers
+                    .read(
+                        &root,
+                        info.file_id,
+                        &state.file_id,
+                        info.start_position,
+                        (info.end_position - info.start_position) as usize,
+                    )
+                    .map_err(|e| corrupted(e.to_string()))?;
+
+                record::decode_record(&buf).map_err(|e| corrupted(e.to_string()))
+            })
+            .await?
+        })
+    }
+
+    /// 记录所有归档文件中仍被索引与历史版本引用的记录
+    fn prepare_merge(&self, state: &GroupState) -> crate::Result<Option<MergePlan>> {
+        let max_id = state.file_id() - 1;
+        if self.archive_ids().is_empty() {
+            return Ok(None);
+        }
+
+        let mut entries: Vec<MergeEntry> = state
+            .index
+            .iter()
+            .filter(|v| v.file_id <= max_id)
+            .map(|v| MergeEntry {
+                key: v.key().clone(),
+                info: v.value().clone(),
+                tombstone: false,
+                current: true,
+            })
+            .collect();
+
+        // 需要保留的历史版本同样复制到新文件中
+        entries.extend(
+            state
+                .history
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, v)| v.info.file_id <= max_id)
+                .map(|(key, v)| MergeEntry {
+                    key: key.clone(),
+                    info: v.info.clone(),
+                    tombstone: v.tombstone,
+                    current: false,
+                }),
+        );
+
+        // 按文件顺序读取，减少随机 IO；同一个 key 的版本也因此保持原有的先后顺序
+        entries.sort_by_key(|v| (v.info.file_id, v.info.start_position));
+        entries.dedup_by_key(|v| (v.info.file_id, v.info.start_position));
+
+        let total_bytes = entries
+            .iter()
+            .map(|v| v.info.end_position - v.info.start_position)
+            .sum();
+
+        state.merge.begin(total_bytes);
+
+        Ok(Some(MergePlan {
+            root: self.root.clone(),
+            epoch: state.epoch.load(Ordering::Acquire),
+            max_id,
+            entries,
+            progress: state.merge.clone(),
+        }))
+    }
+
+    fn finish_merge<'a>(
+        &'a self,
+        state: &'a GroupState,
+        plan: &'a MergePlan,
+        output: MergeOutput,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        Box::pin(async move {
+            let result = self.install(state, plan, output).await;
+            // 归档文件可能已经被替换，之后的读取重新打开文件
+            self.readers.clear();
+
+            if result.is_err() {
+                let _ = tokio::fs::remove_dir_all(self.root.join(MERGE_DIR)).await;
+            }
+
+            result
+        })
+    }
+
+    /// 删除库目录：先改名再删除，删除中途失败也不会留下一个残缺的库
+    fn clean(&self) -> BoxFuture<'_, crate::Result<()>> {
+        Box::pin(async move {
+            let name = self.root.file_name().unwrap_or_default().to_string_lossy();
+            let temp = self.root.with_file_name(format!("~drop-{}", name));
+
+            // 上一次删除失败留下的目录
+            if temp.exists() {
+                tokio::fs::remove_dir_all(&temp).await?;
+            }
+
+            tokio::fs::rename(&self.root, &temp).await?;
+            tokio::fs::remove_dir_all(&temp).await?;
+
+            Ok(())
+        })
+    }
+
+    /// 所有数据文件中记录部分的总大小
+    fn data_size(&self) -> BoxFuture<'_, crate::Result<u64>> {
+        let root = self.root.clone();
+
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                let mut files: Vec<PathBuf> = archive_ids(&root)
+                    .into_iter()
+                    .map(|id| root.join(format!("archive-{}.db", id)))
+                    .collect();
+                files.push(root.join("active.db"));
+
+                let mut total = 0;
+                for path in files {
+                    total += fs::metadata(path)?
+                        .len()
+                        .saturating_sub(record::FILE_HEADER_SIZE);
+                }
+
+                Ok(total)
+            })
+            .await?
+        })
+    }
+
+    fn rotate<'a>(&'a self, state: &'a GroupState) -> BoxFuture<'a, crate::Result<()>> {
+        Box::pin(async move {
+            let mut writer = self.writer.lock().await;
+            self.rotate_active(state, &mut writer).await
+        })
+    }
+
+    /// 复制数据文件：归档文件不会再被修改，直接硬链接；活跃文件只复制已经写入的部分
+    fn fork<'a>(&'a self, state: &'a GroupState, target: PathBuf) -> BoxFuture<'a, crate::Result<()>> {
+        Box::pin(async move {
+            let writer = self.writer.lock().await;
+
+            let root = self.root.clone();
+            let file_id = state.file_id();
+            let active_size = writer.as_ref().map(|v| v.write_position);
+
+            tokio::task::spawn_blocking(move || {
+                let result = fork_files(&root, &target, file_id, active_size);
+                if result.is_err() {
+                    let _ = fs::remove_dir_all(&target);
+                }
+                result
+            })
+            .await?
+        })
+    }
+
+    /// 检查数据文件：活跃文件只检查写入句柄当前位置之前的部分，之后正在写入的记录不会被误报为不完整
+    fn verify<'a>(&'a self, state: &'a GroupState, name: &'a str) -> BoxFuture<'a, crate::Result<VerifyReport>> {
+        Box::pin(async move {
+            // 持有写入句柄期间既不会追加也不会归档，打开的一定是 file_id 对应的活跃文件
+            let (active_id, active) = {
+                let writer = self.writer.lock().await;
+                let mut active = RecordReader::open(&self.root().join("active.db"));
+                if let (Ok(reader), Some(writer)) = (active.as_mut(), writer.as_ref()) {
+                    reader.limit(writer.write_position);
+                }
+                (state.file_id(), active)
+            };
+
+            let (name, root) = (name.to_string(), self.root.clone());
+            tokio::task::spawn_blocking(move || verify_files(&name, &root, active_id, active)).await?
+        })
+    }
+}
+
+/// `db verify` 的检查结果
+#[derive(Serialize, Debug)]
+pub struct VerifyReport {
+    group: String,
+    files: usize,
+    records: usize,
+    problems: Vec<VerifyProblem>,
+    /// 加载时从数据文件中截掉、保存下来等待手动恢复的部分
+    saved: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VerifyProblem {
+    file_id: u32,
+    offset: u64,
+    kind: &'static str,
+    detail: String,
+}
+
+// 快照冻结时存放硬链接的临时目录前缀
+pub(crate) const SNAPSHOT_STAGING_PREFIX: &str = "snapshot-";
+
+// 合并输出的临时目录，写入完成后生成标记文件
+pub(crate) const MERGE_DIR: &str = "merge";
+pub(crate) const MERGE_MARKER: &str = "complete";
+
+/// 合并计划：需要复制的归档文件索引快照
+pub(crate) struct MergePlan {
+    root: PathBuf,
+    epoch: u64,
+    /// 参与合并的最大归档编号，输出文件编号不会超过它
+    max_id: u32,
+    entries: Vec<MergeEntry>,
+    progress: Arc<MergeProgress>,
+}
+
+/// 需要复制的记录：当前值或者需要保留的历史版本
+struct MergeEntry {
+    key: String,
+    info: IndexInfo,
+    tombstone: bool,
+    current: bool,
+}
+
+/// 合并结果
+pub(crate) struct MergeOutput {
+    /// (key, 合并前位置, 合并后位置)
+    moved: Vec<(String, IndexInfo, IndexInfo)>,
+    /// 已过期、没有被复制的记录
+    expired: Vec<(String, IndexInfo)>,
+}
+
+/// 合并输出文件
+struct MergeWriter {
+    dir: PathBuf,
+    file_id: u32,
+    out: std::io::BufWriter<fs::File>,
+    size: u64,
+    entries: Vec<HintEntry>,
+}
+
+impl MergeWriter {
+    fn create(dir: &Path, file_id: u32) -> crate::Result<Self> {
+        let mut out = std::io::BufWriter::new(fs::File::create(
+            dir.join(format!("archive-{}.db", file_id)),
+        )?);
+
+        let header = record::file_header();
+        out.write_all(&header)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            file_id,
+            out,
+            size: header.len() as u64,
+            entries: vec![],
+        })
+    }
+
+    fn finish(self) -> crate::Result<()> {
+        let out = self.out.into_inner().map_err(|e| anyhow!(e.to_string()))?;
+        out.sync_all()?;
+
+        hint::write(&self.dir, self.file_id, self.size, &self.entries)
+    }
+}
+
+impl MergePlan {
+    /// 合并第二阶段（不持有锁）：复制仍然有效且未过期的记录到临时目录
+    ///
+    /// 归档文件不会再被修改，因此这里可以和读写请求并发执行。
+    pub(crate) fn run(&self) -> crate::Result<MergeOutput> {
+        let dir = self.root.join(MERGE_DIR);
+
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+
+        let now = chrono::Local::now().timestamp();
+
+        let mut output = MergeOutput {
+            moved: vec![],
+            expired: vec![],
+        };
+
+        let mut files: HashMap<u32, fs::File> = HashMap::new();
+        let mut writer: Option<MergeWriter> = None;
+        let mut buf = vec![];
+
+        // 当前值已过期的 key 连同历史版本一起丢弃，否则重新加载时旧版本会变回当前值
+        let expired: HashSet<&str> = self
+            .entries
+            .iter()
+            .filter(|v| v.current && is_expired(v.info.time_stamp, now))
+            .map(|v| v.key.as_str())
+            .collect();
+
+        for MergeEntry {
+            key,
+            info,
+            tombstone,
+            current,
+        } in self.entries.iter()
+        {
+            let len = info.end_position - info.start_position;
+
+            if expired.contains(key.as_str()) {
+                if *current {
+                    output.expired.push((key.clone(), info.clone()));
+                }
+                self.progress.scanned_bytes.fetch_add(len, Ordering::Relaxed);
+                continue;
+            }
+
+            let file = match files.entry(info.file_id) {
+                std::collections::hash_map::Entry::Occupied(v) => v.into_mut(),
+                std::collections::hash_map::Entry::Vacant(v) => v.insert(fs::File::open(
+                    self.root.join(format!("archive-{}.db", info.file_id)),
+                )?),
+            };
+
+            buf.resize(len as usize, 0);
+            file.seek(SeekFrom::Start(info.start_position))?;
+            file.read_exact(&mut buf)?;
+
+            // 读取失败（数据损坏）时放弃本次合并，保留原始文件
+            let corrupted = |e: anyhow::Error| {
+                anyhow!(
+                    "data corrupted: file {} offset {}: {}",
+                    info.file_id,
+                    info.start_position,
+                    e
+                )
+            };
+            record::decode_record(&buf).map_err(corrupted)?;
+
+            // 旧密钥加密（或未加密）的记录使用当前密钥重新加密，密钥轮换在合并时完成
+            if let Some(v) = record::rekey_record(&buf).map_err(corrupted)? {
+                buf = v;
+            }
+
+            // 输出文件编号不能超过参与合并的最大编号，超出时继续写入最后一个文件
+            let rotate = match writer {
+                None => true,
+                Some(ref w) => w.size >= ARCHIVE_SIZE && w.file_id < self.max_id,
+            };
+            if rotate {
+                let next = writer.as_ref().map(|w| w.file_id + 1).unwrap_or(1);
+                if let Some(w) = writer.take() {
+                    w.finish()?;
+                }
+                writer = Some(MergeWriter::create(&dir, next)?);
+            }
+
+            let w = writer.as_mut().unwrap();
+
+            let start_position = w.size;
+            w.out.write_all(&buf)?;
+            w.size += buf.len() as u64;
+
+            w.entries.push(HintEntry {
+                key: key.clone(),
+                start_position,
+                end_position: w.size,
+                time_stamp: info.time_stamp,
+                tombstone: *tombstone,
+            });
+
+            output.moved.push((
+                key.clone(),
+                info.clone(),
+                IndexInfo {
+                    file_id: w.file_id,
+                    start_position,
+                    end_position: w.size,
+                    time_stamp: info.time_stamp,
+                    access: Access::default(),
+                },
+            ));
+
+            self.progress.copied_bytes.fetch_add(len, Ordering::Relaxed);
+            self.progress.scanned_bytes.fetch_add(len, Ordering::Relaxed);
+        }
+
+        let count = writer.as_ref().map(|w| w.file_id).unwrap_or(0);
+        if let Some(w) = writer.take() {
+            w.finish()?;
+        }
+
+        // 标记文件写入后，合并结果即使在替换途中崩溃也能在下次启动时继续完成
+        let marker = dir.join(MERGE_MARKER);
+        let temp = dir.join(format!("{}.tmp", MERGE_MARKER));
+        fs::write(&temp, format!("{} {}", self.max_id, count))?;
+        fs::File::open(&temp)?.sync_all()?;
+        fs::rename(&temp, &marker)?;
+
+        Ok(output)
+    }
+}
+
+/// 在 `target` 下生成 `root` 的副本（阻塞操作）
+///
+/// 归档文件与 hint 文件以硬链接与源库共享，之后任何一方都不会再修改它们；
+/// 活跃文件复制前 `active_size` 字节，即写入任务已经写入的部分（没有打开写入器时复制整个文件），
+/// 之后源库继续追加的记录不会出现在副本中。`record.in` 与单库配置一并写入。
+fn fork_files(root: &Path, target: &Path, file_id: u32, active_size: Option<u64>) -> crate::Result<()> {
+    fs::create_dir_all(target)?;
+
+    for id in archive_ids(root) {
+        let name = format!("archive-{}.db", id);
+        fs::hard_link(root.join(&name), target.join(&name))?;
+
+        let hint = hint::hint_path(root, id);
+        if hint.is_file() {
+            fs::hard_link(&hint, hint::hint_path(target, id))?;
+        }
+    }
+
+    let active = root.join("active.db");
+    let active_size = match active_size {
+        Some(v) => v,
+        None => fs::metadata(&active).map(|v| v.len()).unwrap_or(0),
+    };
+
+    let mut output = fs::File::create(target.join("active.db"))?;
+    if active_size > 0 {
+        std::io::copy(&mut fs::File::open(&active)?.take(active_size), &mut output)?;
+    } else {
+        output.write_all(&record::file_header())?;
+    }
+    output.sync_all()?;
+
+    fs::write(target.join("record.in"), file_id.to_string())?;
+
+    let config = root.join(GROUP_CONFIG_FILE);
+    if config.is_file() {
+        fs::copy(&config, target.join(GROUP_CONFIG_FILE))?;
+    }
+
+    Ok(())
+}
+
+/// 将硬链接的归档文件替换为独立的副本
+pub(crate) fn unshare_archives(root: &Path) -> crate::Result<()> {
+    for id in archive_ids(root) {
+        let mut files = vec![root.join(format!("archive-{}.db", id))];
+        let hint = hint::hint_path(root, id);
+        if hint.is_file() {
+            files.push(hint);
+        }
+
+        for path in files {
+            let mut temp = path.clone().into_os_string();
+            temp.push(".copy");
+            let temp = PathBuf::from(temp);
+            fs::copy(&path, &temp)?;
+            fs::File::open(&temp)?.sync_all()?;
+            fs::rename(&temp, &path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// 目录下所有归档文件编号（升序）
+pub(crate) fn archive_ids(root: &Path) -> Vec<u32> {
+    let mut ids = vec![];
+
+    for entry in walkdir::WalkDir::new(root)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        if !entry.path().is_file() {
+            continue;
+        }
+
+        let file_name = entry.file_name().to_string_lossy();
+
+        let info: nom::IResult<&str, &str> = nom::sequence::delimited(
+            nom::bytes::complete::tag("archive-"),
+            nom::character::complete::digit1,
+            nom::bytes::complete::tag(".db"),
+        )(&file_name);
+
+        if let Ok(("", id)) = info {
+            if let Ok(id) = id.parse::<u32>() {
+                ids.push(id);
+            }
+        }
+    }
+
+    ids.sort_unstable();
+    ids
+}
+
+/// 逐条检查数据文件中的记录（阻塞操作）
+pub(crate) fn verify_files(
+    name: &str,
+    root: &Path,
+    active_id: u32,
+    active: crate::Result<RecordReader>,
+) -> crate::Result<VerifyReport> {
+    let mut report = VerifyReport {
+        group: name.to_string(),
+        files: 0,
+        records: 0,
+        problems: vec![],
+        saved: vec![],
+    };
+
+    for entry in fs::read_dir(root)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if name.contains(".db.corrupt-") {
+            report.saved.push(name);
+        }
+    }
+    report.saved.sort();
+
+    // 检查开始后归档的文件（编号不小于 active_id）中的记录已经通过 `active` 检查
+    let files = archive_ids(root)
+        .into_iter()
+        .filter(|id| *id < active_id)
+        .map(|id| (id, RecordReader::open(&root.join(format!("archive-{}.db", id)))))
+        .chain(std::iter::once((active_id, active)));
+
+    for (file_id, reader) in files {
+        let mut reader = match reader {
+            Ok(v) => v,
+            Err(e) => {
+                report.problems.push(VerifyProblem {
+                    file_id,
+                    offset: 0,
+                    kind: "unreadable",
+                    detail: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        report.files += 1;
+
+        while let Some(record) = reader.next_record()? {
+            report.records += 1;
+
+            let problem = match record {
+                ScannedRecord::Node { position, node, .. } => match node.verify() {
+                    Ok(_) => continue,
+                    Err(e) => (position, "corrupt", e.to_string()),
+                },
+                ScannedRecord::Unreadable {
+                    position, reason, ..
+                } => (position, "corrupt", reason),
+                ScannedRecord::Torn { position, len } => {
+                    (position, "torn", format!("{} trailing bytes of incomplete record", len))
+                }
+                ScannedRecord::BadLength { position, len } => (
+                    position,
+                    "corrupt",
+                    format!("damaged record length, {} trailing bytes unreadable", len),
+                ),
+            };
+
+            report.problems.push(VerifyProblem {
+                file_id,
+                offset: problem.0,
+                kind: problem.1,
+                detail: problem.2,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// 按编号顺序重放 `source` 中的归档文件，将截至 `timestamp` 仍然存在的 key 写入 `target`
+///
+/// 删除标记同样参与重放；合并会丢弃旧版本，因此早于最近一次合并的时间点只能恢复出合并后仍保留的记录。
+/// 返回恢复出的 key 数量。
+pub(crate) fn replay_until(source: &Path, timestamp: i64, target: &Path) -> crate::Result<usize> {
+    let ids = archive_ids(source);
+
+    // key -> (文件编号, 起始位置, 结束位置)
+    let mut latest: HashMap<String, (u32, u64, u64)> = HashMap::new();
+
+    for id in ids.iter() {
+        let path = source.join(format!("archive-{}.db", id));
+        let mut reader = RecordReader::open(&path)?;
+
+        while let Some(scanned) = reader.next_record()? {
+            let (position, len, node) = match scanned {
+                ScannedRecord::Node {
+                    position,
+                    len,
+                    node,
+                } => (position, len, node),
+                ScannedRecord::Unreadable {
+                    position, reason, ..
+                } => {
+                    log::warn!("unreadable record skipped in {:?} at {}: {}.", path, position, reason);
+                    continue;
+                }
+                ScannedRecord::Torn { .. } => break,
+                ScannedRecord::BadLength { position, len } => {
+                    log::warn!("damaged record length in {:?} at {}, {} bytes skipped.", path, position, len);
+                    break;
+                }
+            };
+
+            if node.time_stamp.0 > timestamp {
+                continue;
+            }
+
+            if node.value == DataValue::None {
+                latest.remove(&node.key);
+            } else {
+                latest.insert(node.key, (*id, position, position + len));
+            }
+        }
+    }
+
+    let mut entries: Vec<(String, (u32, u64, u64))> = latest.into_iter().collect();
+    entries.sort_by_key(|(_, v)| *v);
+
+    if target.exists() {
+        fs::remove_dir_all(target)?;
+    }
+    fs::create_dir_all(target)?;
+
+    let mut files: HashMap<u32, fs::File> = HashMap::new();
+    let mut writer: Option<MergeWriter> = None;
+    let mut buf = vec![];
+
+    for (key, (id, start, end)) in entries.iter() {
+        let file = match files.entry(*id) {
+            std::collections::hash_map::Entry::Occupied(v) => v.into_mut(),
+            std::collections::hash_map::Entry::Vacant(v) => {
+                v.insert(fs::File::open(source.join(format!("archive-{}.db", id)))?)
+            }
+        };
+
+        buf.resize((end - start) as usize, 0);
+        file.seek(SeekFrom::Start(*start))?;
+        file.read_exact(&mut buf)?;
+
+        let node = record::decode_record(&buf)?;
+
+        // 新库的文件头使用当前密钥，记录也需要使用当前密钥
+        if let Some(v) = record::rekey_record(&buf)? {
+            buf = v;
+        }
+
+        let rotate = match writer {
+            None => true,
+            Some(ref w) => w.size >= ARCHIVE_SIZE,
+        };
+        if rotate {
+            let next = writer.as_ref().map(|w| w.file_id + 1).unwrap_or(1);
+            if let Some(w) = writer.take() {
+                w.finish()?;
+            }
+            writer = Some(MergeWriter::create(target, next)?);
+        }
+
+        let w = writer.as_mut().unwrap();
+
+        let start_position = w.size;
+        w.out.write_all(&buf)?;
+        w.size += buf.len() as u64;
+
+        w.entries.push(HintEntry {
+            key: key.clone(),
+            start_position,
+            end_position: w.size,
+            time_stamp: node.time_stamp,
+            tombstone: false,
+        });
+    }
+
+    let next_id = writer.as_ref().map(|w| w.file_id + 1).unwrap_or(1);
+    if let Some(w) = writer.take() {
+        w.finish()?;
+    }
+
+    fs::write(target.join("record.in"), next_id.to_string())?;
+    fs::write(target.join("active.db"), record::file_header())?;
+
+    Ok(entries.len())
+}
+
+/// 用合并目录中的文件替换编号不超过 `max_id` 的归档文件
+///
+/// 每一步都可以重复执行：已经移动过的文件不会再处理。返回是否执行了替换。
+fn install_merge(root: &Path) -> crate::Result<bool> {
+    let dir = root.join(MERGE_DIR);
+
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+
+    let marker = match fs::read_to_string(dir.join(MERGE_MARKER)) {
+        Ok(v) => v,
+        Err(_) => {
+            fs::remove_dir_all(&dir)?;
+            return Ok(false);
+        }
+    };
+
+    let mut parts = marker.split_whitespace().map(|v| v.parse::<u32>());
+    let (max_id, count) = match (parts.next(), parts.next()) {
+        (Some(Ok(a)), Some(Ok(b))) if b <= a => (a, b),
+        _ => {
+            fs::remove_dir_all(&dir)?;
+            return Err(anyhow!("merge marker of {:?} is invalid", root));
+        }
+    };
+
+    for id in 1..=count {
+        let data = dir.join(format!("archive-{}.db", id));
+        if !data.is_file() {
+            continue;
+        }
+
+        // 先删除旧 hint，避免新数据文件配上旧的 hint
+        let _ = fs::remove_file(hint::hint_path(root, id));
+        fs::rename(&data, root.join(format!("archive-{}.db", id)))?;
+
+        let new_hint = hint::hint_path(&dir, id);
+        if new_hint.is_file() {
+            fs::rename(&new_hint, hint::hint_path(root, id))?;
+        }
+    }
+
+    for id in count + 1..=max_id {
+        let _ = fs::remove_file(root.join(format!("archive-{}.db", id)));
+        let _ = fs::remove_file(hint::hint_path(root, id));
+    }
+
+    fs::remove_dir_all(&dir)?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repair_interrupted_rotation() {
+        let root = std::env::temp_dir().join(format!("dorea-rotation-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+
+        // active.db 已经重命名为 archive-2.db，但 record.in 和新的活跃文件还没有写入
+        fs::write(root.join("archive-1.db"), record::file_header()).unwrap();
+        fs::write(root.join("archive-2.db"), record::file_header()).unwrap();
+        fs::write(root.join("record.in"), b"2").unwrap();
+
+        let file_id = LogStorage::new(&root, false).init_db().unwrap();
+
+        assert_eq!(file_id, 3);
+        assert_eq!(fs::read_to_string(root.join("record.in")).unwrap(), "3");
+        assert!(root.join("active.db").is_file());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,239 @@
+//! 库的存储后端
+//!
+//! 索引、历史版本、过期时间与缓存由 `GroupState` 维护，与存储方式无关；
+//! 存储后端只负责记录本身：加载时重建索引、追加写入、按位置读取、合并回收无效记录以及删除全部数据。
+//!
+//! - `log`: 日志存储，记录追加写入库目录中的数据文件
+//! - `memory`: 记录只保存在内存中，不读写磁盘，用于测试以及临时的缓存库
+
+pub(crate) mod log;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+
+use crate::configure::StorageKind;
+use crate::database::{DataNode, GroupState, IndexInfo, LoadProgress};
+use crate::value::DataValue;
+use self::log::{MergeOutput, MergePlan, VerifyReport};
+
+// 内存存储中每条记录除 key 与数据以外的开销估算（哈希表槽位与 DataNode 本身）
+const RECORD_OVERHEAD: u64 = 96;
+
+/// 内存存储中一条记录占用的内存
+pub(crate) fn record_size(key: &str, value: &DataValue) -> u64 {
+    RECORD_OVERHEAD + key.len() as u64 + value.size() as u64
+}
+
+/// 存储后端
+///
+/// 写入相关的方法只由库的写入任务调用，同一时间只有一个写入在进行；读取可以与写入并发。
+pub(crate) trait StorageBackend: Send + Sync + std::fmt::Debug {
+    fn kind(&self) -> StorageKind;
+
+    /// 读取已有的记录并重建索引（阻塞操作，需要在阻塞线程池中调用）
+    fn load_index(&self, state: &GroupState, progress: &LoadProgress) -> crate::Result<()>;
+
+    /// 追加一批记录，返回每条记录在当前文件（`state.file_id()`）中的起止位置
+    fn write<'a>(
+        &'a self,
+        state: &'a GroupState,
+        nodes: &'a [&'a DataNode],
+    ) -> BoxFuture<'a, crate::Result<Vec<(u64, u64)>>>;
+
+    /// 按索引中的位置读取一条记录
+    fn read(&self, state: &Arc<GroupState>, info: IndexInfo) -> BoxFuture<'static, crate::Result<DataNode>>;
+
+    /// 合并第一阶段（库持有写锁，活跃文件已经归档）：准备回收不再被索引与历史版本引用的记录
+    ///
+    /// 返回在库锁外执行的复制计划（`MergePlan::run`），之后由 `finish_merge` 完成；不需要复制时返回 None。
+    fn prepare_merge(&self, state: &GroupState) -> crate::Result<Option<MergePlan>>;
+
+    /// 合并第三阶段（库持有写锁）：用复制的结果替换数据文件，并更新合并期间没有被修改过的索引
+    fn finish_merge<'a>(
+        &'a self,
+        _state: &'a GroupState,
+        _plan: &'a MergePlan,
+        _output: MergeOutput,
+    ) -> BoxFuture<'a, crate::Result<()>> {
+        let kind = self.kind();
+        Box::pin(async move { Err(anyhow!("merge is not supported by {:?} storage", kind)) })
+    }
+
+    /// 删除全部数据（库已经卸载）
+    fn clean(&self) -> BoxFuture<'_, crate::Result<()>>;
+
+    /// 所有记录（包括无效记录）占用的字节数
+    fn data_size(&self) -> BoxFuture<'_, crate::Result<u64>>;
+
+    /// 结束当前文件的写入，之后的写入进入新文件
+    fn rotate<'a>(&'a self, _state: &'a GroupState) -> BoxFuture<'a, crate::Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// 将当前的数据复制到另一个库目录
+    fn fork<'a>(&'a self, _state: &'a GroupState, _target: PathBuf) -> BoxFuture<'a, crate::Result<()>> {
+        let kind = self.kind();
+        Box::pin(async move { Err(anyhow!("fork is not supported by {:?} storage", kind)) })
+    }
+
+    /// 检查数据文件的完整性
+    fn verify<'a>(&'a self, _state: &'a GroupState, _name: &'a str) -> BoxFuture<'a, crate::Result<VerifyReport>> {
+        let kind = self.kind();
+        Box::pin(async move { Err(anyhow!("verify is not supported by {:?} storage", kind)) })
+    }
+
+    /// 这些记录不再被索引与历史版本引用（由写入任务在更新索引后调用）
+    ///
+    /// 日志存储在合并时统一回收，不需要处理。
+    fn release(&self, _state: &GroupState, _records: &[(u64, u64)]) {}
+}
+
+/// 内存存储：记录按写入顺序编号保存在内存中，不再被引用时立即释放
+///
+/// 记录的位置是累计写入的字节数，因此 `end - start` 与日志存储一样表示记录的大小，
+/// 碎片统计与历史版本的占用统计不需要区分存储方式。
+#[derive(Debug, Default)]
+pub(crate) struct MemoryStorage {
+    records: Mutex<HashMap<u64, DataNode>>,
+    /// 下一条记录的起始位置
+    position: AtomicU64,
+    /// 保存的记录占用的内存
+    bytes: AtomicU64,
+}
+
+impl StorageBackend for MemoryStorage {
+    fn kind(&self) -> StorageKind {
+        StorageKind::Memory
+    }
+
+    fn load_index(&self, _state: &GroupState, _progress: &LoadProgress) -> crate::Result<()> {
+        Ok(())
+    }
+
+    fn write<'a>(
+        &'a self,
+        state: &'a GroupState,
+        nodes: &'a [&'a DataNode],
+    ) -> BoxFuture<'a, crate::Result<Vec<(u64, u64)>>> {
+        let mut positions = Vec::with_capacity(nodes.len());
+        let mut total = 0;
+
+        {
+            let mut records = self.records.lock().unwrap();
+            for node in nodes {
+                let size = record_size(&node.key, &node.value);
+                let start = self.position.fetch_add(size, Ordering::Relaxed);
+                records.insert(start, (*node).clone());
+                positions.push((start, start + size));
+                total += size;
+            }
+        }
+
+        self.bytes.fetch_add(total, Ordering::Relaxed);
+        // 记录本身也计入库的内存占用
+        state.charge(total as i64);
+
+        Box::pin(async move { Ok(positions) })
+    }
+
+    fn read(&self, _state: &Arc<GroupState>, info: IndexInfo) -> BoxFuture<'static, crate::Result<DataNode>> {
+        let result = self
+            .records
+            .lock()
+            .unwrap()
+            .get(&info.start_position)
+            .cloned()
+            .ok_or_else(|| anyhow!("record at {} not found", info.start_position));
+
+        Box::pin(async move { result })
+    }
+
+    /// 不再被引用的记录在写入时已经释放，合并只需要删除已过期的 key；
+    /// 记录不会持久化，因此直接移除索引，不需要写入删除标记
+    fn prepare_merge(&self, state: &GroupState) -> crate::Result<Option<MergePlan>> {
+        let mut count = 0;
+
+        for (key, info) in state.expired(chrono::Local::now().timestamp()) {
+            if let Some(records) = state.remove_expired(&key, &info) {
+                self.release(state, &records);
+                count += 1;
+            }
+        }
+
+        ::log::info!("merge success: {} expired keys removed from memory storage.", count);
+
+        Ok(None)
+    }
+
+    fn clean(&self) -> BoxFuture<'_, crate::Result<()>> {
+        self.records.lock().unwrap().clear();
+        self.bytes.store(0, Ordering::Relaxed);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn data_size(&self) -> BoxFuture<'_, crate::Result<u64>> {
+        let size = self.bytes.load(Ordering::Relaxed);
+        Box::pin(async move { Ok(size) })
+    }
+
+    fn release(&self, state: &GroupState, records: &[(u64, u64)]) {
+        let mut freed = 0;
+
+        {
+            let mut map = self.records.lock().unwrap();
+            for (start, end) in records {
+                if map.remove(start).is_some() {
+                    freed += end - start;
+                }
+            }
+        }
+
+        self.bytes.fetch_sub(freed, Ordering::Relaxed);
+        state.charge(-(freed as i64));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configure::DataBaseConfig;
+    use crate::database::DataBase;
+
+    #[test]
+    fn test_memory_storage() {
+        let root = std::env::temp_dir().join(format!("dorea-memory-{}", std::process::id()));
+        let config: DataBaseConfig = toml::from_str(
+            "default_group = \"default\"\npre_load_group = []\ndefault_storage = \"memory\"",
+        )
+        .unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let db = DataBase::init("cache".into(), root.clone(), config, Default::default())
+                .await
+                .unwrap();
+            assert_eq!(db.storage_kind(), StorageKind::Memory);
+
+            db.set("foo", DataValue::String("bar".into()), 0).await.unwrap();
+            let memory = db.memory();
+            assert!(memory > record_size("foo", &DataValue::String("bar".into())));
+
+            // 被覆盖的记录立即释放，占用不变
+            db.set("foo", DataValue::String("baz".into()), 0).await.unwrap();
+            assert_eq!(db.get("foo").await.unwrap(), Some(DataValue::String("baz".into())));
+            assert_eq!(db.memory(), memory);
+
+            db.delete("foo").await.unwrap();
+            assert_eq!(db.get("foo").await.unwrap(), None);
+            assert_eq!(db.memory(), 0);
+        });
+
+        // 不会在磁盘上创建库目录
+        assert!(!root.join("cache").exists());
+    }
+}